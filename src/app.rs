@@ -1,6 +1,18 @@
-use std::io::{self, Write};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+};
 
-use re_queue::storage_manager::StorageManager;
+use re_queue::{
+    audit::{self, AuditFilter},
+    config::RotationEntry,
+    export::ExportFormat,
+    import::ImportReport,
+    paths::StoragePaths,
+    search::{Matcher, SearchFilter},
+    storage::StorageError,
+    storage_manager::{MergeOptions, StorageManager, StorageManagerError},
+};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum Command {
@@ -13,6 +25,35 @@ enum Command {
     CreateStorage,
     OpenStorage,
     StorageList,
+    Flush,
+    DeleteStorage,
+    RenameStorage,
+    CloneStorage,
+    MergeStorage,
+    MoveRecord,
+    CopyRecord,
+    SetRotation,
+    ConfigureStorage,
+    RepairConfig,
+    Check,
+    CheckRepair,
+    SalvageStorage,
+    Export,
+    Import,
+    Backup,
+    Restore,
+    Snapshot,
+    SnapshotList,
+    RestoreSnapshot,
+    ViewSnapshot,
+    DeleteSnapshot,
+    Undo,
+    Redo,
+    History,
+    Search,
+    BuildIndex,
+    DropIndex,
+    SearchIndex,
 }
 
 impl Command {
@@ -27,6 +68,35 @@ impl Command {
             "create-storage" => Some(Command::CreateStorage),
             "open-storage" => Some(Command::OpenStorage),
             "storage-list" => Some(Command::StorageList),
+            "flush" => Some(Command::Flush),
+            "delete-storage" => Some(Command::DeleteStorage),
+            "rename-storage" => Some(Command::RenameStorage),
+            "clone-storage" => Some(Command::CloneStorage),
+            "merge-storage" => Some(Command::MergeStorage),
+            "move-record" => Some(Command::MoveRecord),
+            "copy-record" => Some(Command::CopyRecord),
+            "set-rotation" => Some(Command::SetRotation),
+            "configure-storage" => Some(Command::ConfigureStorage),
+            "repair-config" => Some(Command::RepairConfig),
+            "check" => Some(Command::Check),
+            "check --repair" => Some(Command::CheckRepair),
+            "salvage-storage" => Some(Command::SalvageStorage),
+            "export" => Some(Command::Export),
+            "import" => Some(Command::Import),
+            "backup" => Some(Command::Backup),
+            "restore" => Some(Command::Restore),
+            "snapshot" => Some(Command::Snapshot),
+            "snapshot-list" => Some(Command::SnapshotList),
+            "restore-snapshot" => Some(Command::RestoreSnapshot),
+            "view-snapshot" => Some(Command::ViewSnapshot),
+            "delete-snapshot" => Some(Command::DeleteSnapshot),
+            "undo" => Some(Command::Undo),
+            "redo" => Some(Command::Redo),
+            "history" => Some(Command::History),
+            "search" => Some(Command::Search),
+            "build-index" => Some(Command::BuildIndex),
+            "drop-index" => Some(Command::DropIndex),
+            "search-index" => Some(Command::SearchIndex),
             _ => None,
         }
     }
@@ -42,10 +112,10 @@ pub struct App {
 }
 
 impl App {
-    pub fn new() -> Self {
-        Self {
-            storage_manager: StorageManager::new(),
-        }
+    pub fn new(paths: StoragePaths) -> Result<Self, StorageManagerError> {
+        Ok(Self {
+            storage_manager: StorageManager::new(paths)?,
+        })
     }
 
     pub fn run(&mut self) {
@@ -53,6 +123,10 @@ impl App {
 
         let mut mode = Mode::AwaitCommand;
 
+        for (storage_name, e) in self.storage_manager.invalid_storage_names() {
+            println!("Warning: the name of storage '{storage_name}' is no longer allowed ({e}), rename it with rename-storage.");
+        }
+
         if !self.storage_manager.has_storages() {
             println!("You don't have any storages, please create one.");
             mode = Mode::AwaitValue(Command::CreateStorage);
@@ -61,8 +135,19 @@ impl App {
         loop {
             match mode {
                 Mode::AwaitCommand => {
-                    println!("");
-                    println!("Current storage: {}", self.storage_manager.get_active_storage_name());
+                    println!();
+                    match self.storage_manager.get_active_storage_name() {
+                        Ok(storage_name) => println!("Current storage: {storage_name}"),
+                        Err(_) => println!("No storage is open, use open-storage."),
+                    }
+                    if self.storage_manager.is_rotating() {
+                        let rotation: Vec<String> = self.storage_manager
+                            .get_rotation()
+                            .iter()
+                            .map(|entry| format!("{}={}", entry.storage_name, entry.weight))
+                            .collect();
+                        println!("Rotating: {}", rotation.join(", "));
+                    }
                     print!("Write command (help to list): ");
                     io::stdout().flush().unwrap();
 
@@ -71,26 +156,74 @@ impl App {
                     match Command::parse(&line) {
                         Some(Command::Exit) => break,
                         Some(Command::Help) => {
-                            println!("Available commands: save, pick, next, exit, help, list, create-storage, open-storage, storage-list");
+                            println!("Available commands: save, pick, next, exit, help, list, create-storage, open-storage, storage-list, flush, delete-storage, rename-storage, clone-storage, merge-storage, move-record, copy-record, set-rotation, configure-storage, repair-config, check [--repair], salvage-storage, export, import, backup, restore, snapshot, snapshot-list, restore-snapshot, view-snapshot, delete-snapshot, undo, redo, history, search, build-index, drop-index, search-index");
+                        }
+                        Some(Command::Pick) if self.storage_manager.is_rotating() => {
+                            match self.storage_manager.pick_rotation() {
+                                Ok((storage_name, value)) => println!("[{storage_name}] {value}"),
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(Command::MoveNext) if self.storage_manager.is_rotating() => {
+                            match self.storage_manager.move_next_rotation() {
+                                Ok(()) => println!("<next>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
                         }
                         Some(Command::Pick) => {
-                            let value = self.storage_manager.get_active_storage().pick().unwrap();
-                            println!("{value}");
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.pick()?)) {
+                                Ok(value) => println!("{value}"),
+                                Err(e) => println!("Error: {e}"),
+                            }
                         }
                         Some(Command::MoveNext) => {
-                            self.storage_manager.get_active_storage().move_next().unwrap();
-                            println!("<next>");
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.move_next()?)) {
+                                Ok(()) => println!("<next>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
                         }
                         Some(Command::Save) => {
                             mode = Mode::AwaitValue(Command::Save);
                         }
+                        Some(command @ (Command::Undo | Command::Redo)) => {
+                            let result = self.storage_manager.get_active_storage().and_then(|s| {
+                                Ok(if command == Command::Undo { s.undo()? } else { s.redo()? })
+                            });
+
+                            match result {
+                                Ok(entry) => {
+                                    let verb = if command == Command::Undo { "undo" } else { "redo" };
+                                    match entry.record {
+                                        Some((_, id)) => println!("<{verb} {} ({id})>", entry.operation),
+                                        None => println!("<{verb} {}>", entry.operation),
+                                    }
+                                }
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
                         Some(Command::List) => {
-                            let records = self.storage_manager.get_active_storage().get_all().unwrap();
-                            println!("*******");
-                            for record in records {
-                                println!("({}): {}", record.meta.get_id(), record.data);
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.get_all()?)) {
+                                Ok(records) => {
+                                    println!("*******");
+                                    for record in records.iter().filter(|r| r.meta.is_active()) {
+                                        println!("({}): {}", record.meta.get_id(), record.data);
+                                    }
+                                    println!("*******");
+                                }
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(Command::SnapshotList) => {
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.get_snapshots()?)) {
+                                Ok(snapshots) => {
+                                    println!("*******");
+                                    for snapshot in snapshots {
+                                        println!("{}", snapshot.name);
+                                    }
+                                    println!("*******");
+                                }
+                                Err(e) => println!("Error: {e}"),
                             }
-                            println!("*******");
                         }
                         Some(Command::CreateStorage) => {
                             mode = Mode::AwaitValue(Command::CreateStorage);
@@ -106,6 +239,85 @@ impl App {
                             }
                             println!("*******");
                         }
+                        Some(Command::BuildIndex) => {
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.build_index()?)) {
+                                Ok(count) => println!("Indexed {count} records\n<build-index>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(Command::DropIndex) => {
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.drop_index()?)) {
+                                Ok(()) => println!("<drop-index>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(Command::Flush) => {
+                            match self.storage_manager.get_active_storage().and_then(|s| Ok(s.flush()?)) {
+                                Ok(()) => println!("<flush>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(Command::RepairConfig) => {
+                            match self.storage_manager.discover() {
+                                Ok(report) => {
+                                    for storage_name in &report.registered {
+                                        println!("Registered: {storage_name}");
+                                    }
+                                    for storage_name in &report.missing {
+                                        println!("Files missing: {storage_name}");
+                                    }
+                                    for storage_name in &report.incomplete {
+                                        println!("Incomplete, not registered: {storage_name}");
+                                    }
+                                    println!("<repair-config>");
+                                }
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(command @ (Command::Check | Command::CheckRepair)) => {
+                            let result = self.storage_manager.get_active_storage().and_then(|s| {
+                                Ok(if command == Command::CheckRepair { s.repair()? } else { s.verify()? })
+                            });
+
+                            match result {
+                                Ok(report) => {
+                                    println!("Records: {} ({} active)", report.records, report.active_records);
+                                    for issue in &report.issues {
+                                        println!("Issue: {issue}");
+                                    }
+                                    if report.is_ok() {
+                                        println!("No issues found.");
+                                    } else if command == Command::CheckRepair {
+                                        println!("Repaired what could be repaired.");
+                                    } else {
+                                        println!("Run check --repair to fix them.");
+                                    }
+                                }
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Some(command @ (Command::DeleteStorage
+                            | Command::RenameStorage
+                            | Command::CloneStorage
+                            | Command::MergeStorage
+                            | Command::MoveRecord
+                            | Command::CopyRecord
+                            | Command::SetRotation
+                            | Command::ConfigureStorage
+                            | Command::SalvageStorage
+                            | Command::Export
+                            | Command::Import
+                            | Command::Backup
+                            | Command::Restore
+                            | Command::Snapshot
+                            | Command::RestoreSnapshot
+                            | Command::ViewSnapshot
+                            | Command::History
+                            | Command::Search
+                            | Command::SearchIndex
+                            | Command::DeleteSnapshot)) => {
+                            mode = Mode::AwaitValue(command);
+                        }
                         None => {
                             println!("Unknown command: {line}");
                         }
@@ -116,9 +328,10 @@ impl App {
                     io::stdout().flush().unwrap();
 
                     let value = Self::read_line_trimmed_end().unwrap();
-                    self.storage_manager.get_active_storage().save(value).unwrap();
-
-                    println!("<save>");
+                    match self.storage_manager.get_active_storage().and_then(|s| Ok(s.save(value)?)) {
+                        Ok(()) => println!("<save>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
 
                     mode = Mode::AwaitCommand;
                 }
@@ -127,10 +340,14 @@ impl App {
                     io::stdout().flush().unwrap();
 
                     let value = Self::read_line_trimmed_end().unwrap();
-                    self.storage_manager.create(&value);
-                    self.storage_manager.open(&value);
+                    let result = self.storage_manager
+                        .create(&value)
+                        .and_then(|_| self.storage_manager.open(&value));
 
-                    println!("<create-storage>");
+                    match result {
+                        Ok(()) => println!("<create-storage>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
 
                     mode = Mode::AwaitCommand;
                 }
@@ -139,9 +356,326 @@ impl App {
                     io::stdout().flush().unwrap();
 
                     let value = Self::read_line_trimmed_end().unwrap();
-                    self.storage_manager.open(&value);
 
-                    println!("<open-storage>");
+                    match self.storage_manager.open(&value) {
+                        Ok(()) => println!("<open-storage>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::DeleteStorage) => {
+                    let storage_name = Self::prompt("Write storage name: ");
+                    let answer = Self::prompt(&format!("Delete '{storage_name}' and all its records? (y/n): "));
+
+                    if answer == "y" {
+                        match self.storage_manager.delete(&storage_name) {
+                            Ok(()) => println!("<delete-storage>"),
+                            Err(e) => println!("Error: {e}"),
+                        }
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::RenameStorage) => {
+                    let storage_name = Self::prompt("Write storage name: ");
+                    let new_storage_name = Self::prompt("Write new storage name: ");
+
+                    match self.storage_manager.rename(&storage_name, &new_storage_name) {
+                        Ok(()) => println!("<rename-storage>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::CloneStorage) => {
+                    let storage_name = Self::prompt("Write storage name: ");
+                    let new_storage_name = Self::prompt("Write new storage name: ");
+
+                    match self.storage_manager.clone_storage(&storage_name, &new_storage_name) {
+                        Ok(()) => println!("<clone-storage>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::SalvageStorage) => {
+                    let storage_name = Self::prompt("Write damaged storage name: ");
+                    let new_storage_name = Self::prompt("Write new storage name: ");
+
+                    match self.storage_manager.salvage(&storage_name, &new_storage_name) {
+                        Ok(report) => {
+                            println!("Recovered {} records", report.recovered);
+                            for (start, end) in &report.damaged {
+                                println!("Skipped damaged bytes {start}..{end}");
+                            }
+                            println!("<salvage-storage>");
+                        }
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::Export) => {
+                    let format = Self::prompt("Write format (jsonl, csv or text): ");
+                    let path = Self::prompt("Write file path (empty to print): ");
+
+                    match format.parse::<ExportFormat>() {
+                        Ok(format) => match self.export(format, &path) {
+                            Ok(count) => println!("Exported {count} records\n<export>"),
+                            Err(e) => println!("Error: {e}"),
+                        },
+                        Err(()) => println!("Unknown format: {format}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::Import) => {
+                    let format = Self::prompt("Write format (jsonl, csv or text): ");
+                    let storage_name = Self::prompt("Write storage name (empty for the current one): ");
+                    let dedupe = Self::prompt("Skip values already in the storage? (y/n): ") == "y";
+                    let path = Self::prompt("Write file path (empty to paste, then a line with only '.'): ");
+
+                    match format.parse::<ExportFormat>() {
+                        Ok(format) => match self.import(format, &storage_name, dedupe, &path) {
+                            Ok(report) => println!("Added {}, skipped {}\n<import>", report.added, report.skipped),
+                            Err(e @ StorageManagerError::Storage(StorageError::AfterImport { .. })) => println!("Error: {e}"),
+                            Err(e) => println!("Error: {e}, nothing was imported"),
+                        },
+                        Err(()) => println!("Unknown format: {format}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::Backup) => {
+                    let path = Self::prompt("Write archive path: ");
+
+                    match self.backup(&path) {
+                        Ok(count) => println!("Backed up {count} storages\n<backup>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::Restore) => {
+                    let path = Self::prompt("Write archive path: ");
+                    let answer = Self::prompt("Replace the config and storages with the archive? Undo history is lost (y/n): ");
+
+                    if answer == "y" {
+                        let result = File::open(&path)
+                            .map_err(StorageManagerError::from)
+                            .and_then(|file| self.storage_manager.restore(&mut BufReader::new(file)));
+
+                        match result {
+                            Ok(report) => {
+                                println!("Restored: {}", report.restored.join(", "));
+                                if !report.unregistered.is_empty() {
+                                    println!("No longer registered, files kept: {}", report.unregistered.join(", "));
+                                }
+                                println!("<restore>");
+                            }
+                            Err(e) => println!("Error: {e}"),
+                        }
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::Snapshot) => {
+                    let name = Self::prompt("Write snapshot name: ");
+
+                    match self.storage_manager.get_active_storage().and_then(|s| Ok(s.create_snapshot(&name)?)) {
+                        Ok(()) => println!("<snapshot>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::RestoreSnapshot) => {
+                    let name = Self::prompt("Write snapshot name: ");
+                    let answer = Self::prompt(&format!("Drop everything saved since '{name}'? (y/n): "));
+
+                    if answer == "y" {
+                        match self.storage_manager.get_active_storage().and_then(|s| Ok(s.restore_snapshot(&name)?)) {
+                            Ok(dropped) => {
+                                if !dropped.is_empty() {
+                                    println!("Deleted later snapshots: {}", dropped.join(", "));
+                                }
+                                println!("<restore-snapshot>");
+                            }
+                            Err(e) => println!("Error: {e}"),
+                        }
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::ViewSnapshot) => {
+                    let name = Self::prompt("Write snapshot name: ");
+                    let result = self
+                        .storage_manager
+                        .get_active_storage()
+                        .and_then(|s| Ok(s.open_snapshot(&name)?.get_all()?));
+
+                    match result {
+                        Ok(records) => {
+                            println!("*******");
+                            for record in records.iter().filter(|r| r.meta.is_active()) {
+                                println!("({}): {}", record.meta.get_id(), record.data);
+                            }
+                            println!("*******");
+                        }
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::Search) => {
+                    let text = Self::prompt("Write text to search for: ");
+                    let options = Self::prompt(
+                        "Write options (regex, ignore-case, id=FROM-TO, active=yes|no|all; empty for none)\n\
+                         regex knows . [a-z] \\d \\w \\s ^ $ (a|b) * + ?; {n,m} is literal, lazy quantifiers are refused: ",
+                    );
+
+                    match Self::parse_search_filter(&text, &options) {
+                        Ok(filter) => match self.storage_manager.get_active_storage().and_then(|s| Ok(s.search(&filter)?)) {
+                            Ok(records) => {
+                                println!("*******");
+                                for record in &records {
+                                    let state = if record.meta.is_active() { "" } else { " [removed]" };
+                                    println!("({}){state}: {}", record.meta.get_id(), record.data);
+                                }
+                                println!("*******");
+                                println!("{} found", records.len());
+                            }
+                            Err(e) => println!("Error: {e}"),
+                        },
+                        Err(message) => println!("Error: {message}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::SearchIndex) => {
+                    let query = Self::prompt("Write words to search for: ");
+                    let prefix = Self::prompt("Match words starting with them too? (y/n): ") == "y";
+
+                    match self.storage_manager.get_active_storage().and_then(|s| Ok(s.search_index(&query, prefix)?)) {
+                        Ok(records) => {
+                            println!("*******");
+                            for record in &records {
+                                println!("({}): {}", record.meta.get_id(), record.data);
+                            }
+                            println!("*******");
+                            println!("{} found", records.len());
+                        }
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::History) => {
+                    let filters = Self::prompt(
+                        "Write filters (storage=, action=, id=, user=, since=YYYY-MM-DD, last=N; empty for all): ",
+                    );
+
+                    match Self::parse_history_filter(&filters) {
+                        Some(filter) => match self.storage_manager.history(&filter) {
+                            Ok(entries) => {
+                                println!("*******");
+                                for entry in entries {
+                                    let record = entry.record_id.map_or(String::new(), |id| format!(" ({id})"));
+                                    let line = format!(
+                                        "{} {} {} {}{record} {}",
+                                        audit::format_timestamp(entry.timestamp),
+                                        entry.user,
+                                        entry.action,
+                                        entry.storage_name,
+                                        entry.detail
+                                    );
+                                    println!("{}", line.trim_end());
+                                }
+                                println!("*******");
+                            }
+                            Err(e) => println!("Error: {e}"),
+                        },
+                        None => println!("Invalid filters: {filters}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::DeleteSnapshot) => {
+                    let name = Self::prompt("Write snapshot name: ");
+
+                    match self.storage_manager.get_active_storage().and_then(|s| Ok(s.delete_snapshot(&name)?)) {
+                        Ok(()) => println!("<delete-snapshot>"),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::MergeStorage) => {
+                    let source = Self::prompt("Write source storage name: ");
+                    let destination = Self::prompt("Write destination storage name: ");
+                    let options = MergeOptions {
+                        keep_ids: Self::prompt("Keep record ids? (y/n): ") == "y",
+                        dedupe: Self::prompt("Skip duplicate values? (y/n): ") == "y",
+                        delete_source: Self::prompt("Delete source afterwards? (y/n): ") == "y",
+                    };
+
+                    match self.storage_manager.merge(&source, &destination, options) {
+                        Ok(report) => println!(
+                            "<merge-storage> added: {}, skipped: {}, reassigned: {}",
+                            report.added, report.skipped, report.reassigned
+                        ),
+                        Err(e) => println!("Error: {e}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(command @ (Command::MoveRecord | Command::CopyRecord)) => {
+                    let destination = Self::prompt("Write destination storage name: ");
+                    let id = Self::prompt("Write record id (empty for current): ");
+
+                    let result = match id.trim() {
+                        "" => Ok(None),
+                        id => id.parse::<u64>().map(Some),
+                    };
+
+                    match result {
+                        Ok(id) if command == Command::MoveRecord => {
+                            match self.storage_manager.move_record(&destination, id) {
+                                Ok(()) => println!("<move-record>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Ok(id) => {
+                            match self.storage_manager.copy_record(&destination, id) {
+                                Ok(()) => println!("<copy-record>"),
+                                Err(e) => println!("Error: {e}"),
+                            }
+                        }
+                        Err(_) => println!("Invalid record id: {id}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::SetRotation) => {
+                    let value = Self::prompt("Write storages to rotate (name or name=weight, comma separated, empty to stop): ");
+
+                    match Self::parse_rotation(&value) {
+                        Some(rotation) => match self.storage_manager.set_rotation(rotation) {
+                            Ok(()) => println!("<set-rotation>"),
+                            Err(e) => println!("Error: {e}"),
+                        },
+                        None => println!("Invalid rotation: {value}"),
+                    }
+
+                    mode = Mode::AwaitCommand;
+                }
+                Mode::AwaitValue(Command::ConfigureStorage) => {
+                    match self.configure_active_storage() {
+                        Ok(true) => println!("<configure-storage>"),
+                        Ok(false) => {}
+                        Err(e) => println!("Error: {e}"),
+                    }
 
                     mode = Mode::AwaitCommand;
                 }
@@ -154,6 +688,169 @@ impl App {
         println!("=======");
     }
 
+    /// Prompts for the settings of the active storage. Returns `false` if
+    /// the input was invalid and nothing changed.
+    fn configure_active_storage(&mut self) -> Result<bool, StorageManagerError> {
+        let storage_name = self.storage_manager.get_active_storage_name()?;
+        let mut settings = self.storage_manager.get_storage_settings(&storage_name)?;
+
+        let durability = Self::prompt(&format!(
+            "Write durability (always, never, every_ops:<n>, op_after_ms:<ms>) [{}]: ",
+            settings.durability
+        ));
+        if !durability.is_empty() {
+            match durability.parse() {
+                Ok(durability) => settings.durability = durability,
+                Err(()) => {
+                    println!("Invalid durability: {durability}");
+                    return Ok(false);
+                }
+            }
+        }
+
+        let current_max_records = settings.max_records.map_or("none".to_string(), |n| n.to_string());
+        let max_records = Self::prompt(&format!("Write max records (number or none) [{current_max_records}]: "));
+        match max_records.as_str() {
+            "" => {}
+            "none" => settings.max_records = None,
+            value => match value.parse() {
+                Ok(n) if n > 0 => settings.max_records = Some(n),
+                _ => {
+                    println!("Invalid max records: {value}");
+                    return Ok(false);
+                }
+            },
+        }
+
+        self.storage_manager.set_storage_settings(&storage_name, settings)?;
+
+        Ok(true)
+    }
+
+    fn export(&mut self, format: ExportFormat, path: &str) -> Result<u64, StorageManagerError> {
+        let storage = self.storage_manager.get_active_storage()?;
+
+        if path.is_empty() {
+            return Ok(storage.export(&mut io::stdout().lock(), format)?);
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        let count = storage.export(&mut file, format)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        Ok(count)
+    }
+
+    fn backup(&mut self, path: &str) -> Result<u64, StorageManagerError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let count = self.storage_manager.backup(&mut file)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        Ok(count)
+    }
+
+    fn import(
+        &mut self,
+        format: ExportFormat,
+        storage_name: &str,
+        dedupe: bool,
+        path: &str,
+    ) -> Result<ImportReport, StorageManagerError> {
+        let storage_name = (!storage_name.is_empty()).then_some(storage_name);
+
+        if !path.is_empty() {
+            let file = BufReader::new(File::open(path)?);
+            return self.storage_manager.import(storage_name, file, format, dedupe);
+        }
+
+        // Commands come from stdin too, so pasted input needs an end marker
+        let mut pasted = String::new();
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if line == "." {
+                break;
+            }
+            pasted.push_str(&line);
+            pasted.push('\n');
+        }
+
+        self.storage_manager.import(storage_name, pasted.as_bytes(), format, dedupe)
+    }
+
+    fn parse_rotation(value: &str) -> Option<Vec<RotationEntry>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| match part.rsplit_once('=') {
+                Some((storage_name, weight)) => Some(RotationEntry {
+                    storage_name: storage_name.trim().to_string(),
+                    weight: weight.trim().parse().ok()?,
+                }),
+                None => Some(RotationEntry { storage_name: part.to_string(), weight: 1 }),
+            })
+            .collect()
+    }
+
+    /// Only active records unless `active=` says otherwise.
+    fn parse_search_filter(text: &str, options: &str) -> Result<SearchFilter, String> {
+        let mut is_regex = false;
+        let mut ignore_case = false;
+        let mut filter = SearchFilter { is_active: Some(true), ..SearchFilter::default() };
+
+        for option in options.split_whitespace() {
+            match option.split_once('=') {
+                None if option == "regex" => is_regex = true,
+                None if option == "ignore-case" => ignore_case = true,
+                Some(("id", range)) => {
+                    let (from, to) = range.split_once('-').unwrap_or((range, range));
+                    match (from.parse(), to.parse()) {
+                        (Ok(from), Ok(to)) => filter.ids = Some(from..=to),
+                        _ => return Err(format!("invalid id range: {range}")),
+                    }
+                }
+                Some(("active", "yes")) => filter.is_active = Some(true),
+                Some(("active", "no")) => filter.is_active = Some(false),
+                Some(("active", "all")) => filter.is_active = None,
+                _ => return Err(format!("unknown option: {option}")),
+            }
+        }
+
+        filter.matcher = if is_regex {
+            Matcher::regex(text, ignore_case).map_err(|e| e.to_string())?
+        } else {
+            Matcher::substring(text, ignore_case)
+        };
+
+        Ok(filter)
+    }
+
+    fn parse_history_filter(value: &str) -> Option<AuditFilter> {
+        let mut filter = AuditFilter::default();
+
+        for part in value.split_whitespace() {
+            let (key, value) = part.split_once('=')?;
+            match key {
+                "storage" => filter.storage_name = Some(value.to_string()),
+                "action" => filter.action = Some(value.parse().ok()?),
+                "id" => filter.record_id = Some(value.parse().ok()?),
+                "user" => filter.user = Some(value.to_string()),
+                "since" => filter.since = Some(audit::parse_date(value)?),
+                "last" => filter.last = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+
+        Some(filter)
+    }
+
+    fn prompt(message: &str) -> String {
+        print!("{message}");
+        io::stdout().flush().unwrap();
+
+        Self::read_line_trimmed_end().unwrap()
+    }
+
     fn read_line_trimmed() -> io::Result<String> {
         let mut s = String::new();
         io::stdin().read_line(&mut s)?;
@@ -0,0 +1,299 @@
+//! Append-only log of who did what to which storage, and when.
+//!
+//! One line per action, with the fields separated by tabs:
+//!
+//! ```text
+//! <ms since epoch> <user> <action> <storage> <record id or -> <detail>
+//! ```
+
+use std::{
+    env, fmt,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::record::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Save,
+    Pick,
+    Next,
+    Remove,
+    Undo,
+    Redo,
+    Create,
+    Open,
+    Delete,
+    Rename,
+    Clone,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Save => write!(f, "save"),
+            Action::Pick => write!(f, "pick"),
+            Action::Next => write!(f, "next"),
+            Action::Remove => write!(f, "remove"),
+            Action::Undo => write!(f, "undo"),
+            Action::Redo => write!(f, "redo"),
+            Action::Create => write!(f, "create"),
+            Action::Open => write!(f, "open"),
+            Action::Delete => write!(f, "delete"),
+            Action::Rename => write!(f, "rename"),
+            Action::Clone => write!(f, "clone"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "save" => Ok(Action::Save),
+            "pick" => Ok(Action::Pick),
+            "next" => Ok(Action::Next),
+            "remove" => Ok(Action::Remove),
+            "undo" => Ok(Action::Undo),
+            "redo" => Ok(Action::Redo),
+            "create" => Ok(Action::Create),
+            "open" => Ok(Action::Open),
+            "delete" => Ok(Action::Delete),
+            "rename" => Ok(Action::Rename),
+            "clone" => Ok(Action::Clone),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub user: String,
+    pub action: Action,
+    pub storage_name: String,
+    pub record_id: Option<u64>,
+    /// E.g. the new name of a renamed or cloned storage, or the operation
+    /// undone.
+    pub detail: String,
+}
+
+impl AuditEntry {
+    fn to_line(&self) -> String {
+        let record_id = self.record_id.map_or("-".to_string(), |id| id.to_string());
+        format!(
+            "{}\t{}\t{}\t{}\t{record_id}\t{}\n",
+            self.timestamp,
+            sanitize(&self.user),
+            self.action,
+            sanitize(&self.storage_name),
+            sanitize(&self.detail)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(6, '\t');
+        let timestamp = fields.next()?.parse().ok()?;
+        let user = fields.next()?.to_string();
+        let action = fields.next()?.parse().ok()?;
+        let storage_name = fields.next()?.to_string();
+        let record_id = match fields.next()? {
+            "-" => None,
+            id => Some(id.parse().ok()?),
+        };
+        let detail = fields.next()?.to_string();
+
+        Some(Self { timestamp, user, action, storage_name, record_id, detail })
+    }
+}
+
+/// Which entries `AuditLog::read` returns. Unset fields match anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    pub storage_name: Option<String>,
+    pub action: Option<Action>,
+    pub record_id: Option<u64>,
+    pub user: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub since: Option<u64>,
+    /// Only the most recent this many matches.
+    pub last: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.storage_name.as_ref().is_none_or(|name| *name == entry.storage_name)
+            && self.action.is_none_or(|action| action == entry.action)
+            && self.record_id.is_none_or(|id| Some(id) == entry.record_id)
+            && self.user.as_ref().is_none_or(|user| *user == entry.user)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    user: String,
+}
+
+impl AuditLog {
+    /// Logs as the user named by `$USER` (`$USERNAME` on Windows).
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+
+        Self::with_user(path, user)
+    }
+
+    pub fn with_user(path: impl AsRef<Path>, user: impl Into<String>) -> Self {
+        Self { path: path.as_ref().to_path_buf(), user: user.into() }
+    }
+
+    /// Appends an entry. Each one goes out in a single write, so processes
+    /// sharing the log don't interleave their lines.
+    pub fn record(
+        &self,
+        action: Action,
+        storage_name: &str,
+        record_id: Option<u64>,
+        detail: &str,
+    ) -> std::io::Result<()> {
+        let entry = AuditEntry {
+            timestamp: Record::now(),
+            user: self.user.clone(),
+            action,
+            storage_name: storage_name.to_string(),
+            record_id,
+            detail: detail.to_string(),
+        };
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(entry.to_line().as_bytes())
+    }
+
+    /// Entries matching `filter`, oldest first. Lines that can't be read are
+    /// skipped.
+    pub fn read(&self, filter: &AuditFilter) -> std::io::Result<Vec<AuditEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Some(entry) = AuditEntry::from_line(&line?).filter(|entry| filter.matches(entry)) {
+                entries.push(entry);
+            }
+        }
+
+        if let Some(last) = filter.last {
+            entries.drain(..entries.len().saturating_sub(last));
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Tabs and line breaks would split the entry.
+fn sanitize(value: &str) -> String {
+    value.chars().map(|c| if c.is_control() { '?' } else { c }).collect()
+}
+
+/// `YYYY-MM-DD HH:MM:SS`, in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp / 1000;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+
+    format!("{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}", time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// Start of a `YYYY-MM-DD` day in UTC, as milliseconds since the Unix epoch.
+pub fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days).ok().map(|days| days * 86_400_000)
+}
+
+// Howard Hinnant's algorithms for the proleptic Gregorian calendar.
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_round_trip() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+
+        let leap_day = parse_date("2024-02-29").unwrap();
+        assert_eq!(format_timestamp(leap_day + 3_723_000), "2024-02-29 01:02:03");
+        assert_eq!(parse_date("2024-13-01"), None);
+    }
+
+    #[test]
+    fn filters_pick_matching_entries() {
+        let dir = env::temp_dir().join(format!("re-queue-audit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let log = AuditLog::with_user(dir.join("audit.log"), "ann\tb");
+        log.record(Action::Save, "inbox", Some(42), "").unwrap();
+        log.record(Action::Pick, "inbox", Some(42), "").unwrap();
+        log.record(Action::Pick, "inbox", Some(7), "").unwrap();
+        log.record(Action::Rename, "inbox", None, "done").unwrap();
+
+        let picks = log
+            .read(&AuditFilter { action: Some(Action::Pick), record_id: Some(42), ..AuditFilter::default() })
+            .unwrap();
+        assert_eq!(picks.len(), 1);
+        assert_eq!(picks[0].user, "ann?b");
+
+        let last = log.read(&AuditFilter { last: Some(1), ..AuditFilter::default() }).unwrap();
+        assert_eq!((last[0].action, last[0].detail.as_str()), (Action::Rename, "done"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    data_store::DataStore,
+    file_lock::{FileLock, LockKind},
+    meta::Meta,
+    memory_backend::MemoryLock,
+    meta_store::MetaStore,
+    notifier::Notifier,
+    record::Record,
+};
+
+/// What `Storage` needs from the place its meta and records live.
+///
+/// Pointers are byte offsets into the record stream, so every backend lays
+/// records out exactly like the `.dt` file does.
+pub trait StorageBackend: Send {
+    fn get_meta(&mut self) -> std::io::Result<Meta>;
+    fn update_meta(&mut self, meta: Meta) -> std::io::Result<()>;
+
+    /// Appends record and returns number of bytes written
+    fn push(&mut self, record: &Record) -> std::io::Result<u64>;
+    fn pick(&mut self, pointer: u64) -> std::io::Result<Record>;
+    fn get_all(&mut self) -> std::io::Result<Vec<Record>>;
+
+    /// Size of the record stream, which may run past the write pointer
+    /// after a crash.
+    fn data_len(&mut self) -> std::io::Result<u64>;
+    /// Raw bytes of the record stream, for checking it without trusting it.
+    fn read_at(&mut self, pointer: u64, len: usize) -> std::io::Result<Vec<u8>>;
+    fn truncate(&mut self, len: u64) -> std::io::Result<()>;
+    fn set_active(&mut self, pointer: u64, is_active: bool) -> std::io::Result<()>;
+
+    /// Forces written meta and records to durable media.
+    fn sync(&mut self) -> std::io::Result<()>;
+
+    /// Guards the storage against every other handle on it. Contention past
+    /// `timeout` is reported as `ErrorKind::WouldBlock`.
+    fn lock(&mut self, kind: LockKind, timeout: Duration) -> std::io::Result<BackendLock>;
+
+    /// Notifier shared by every handle on the same underlying storage.
+    fn notifier(&self) -> Arc<Notifier>;
+
+    /// Extra state kept alongside the records, such as snapshots, by name.
+    /// `None` if nothing was written under that name yet.
+    fn read_sidecar(&mut self, name: &str) -> std::io::Result<Option<Vec<u8>>>;
+    /// Size of a sidecar in bytes, `None` if it wasn't written yet.
+    fn sidecar_len(&mut self, name: &str) -> std::io::Result<Option<u64>>;
+    /// Up to `len` bytes of a sidecar from `offset` on; fewer past its end.
+    fn read_sidecar_range(&mut self, name: &str, offset: u64, len: usize) -> std::io::Result<Vec<u8>>;
+    /// Replaces a sidecar as a whole; readers see either the old or the new content.
+    fn write_sidecar(&mut self, name: &str, content: &[u8]) -> std::io::Result<()>;
+    /// Adds to the end of a sidecar, creating it if needed. Not synced; a
+    /// crash may lose the tail.
+    fn append_sidecar(&mut self, name: &str, content: &[u8]) -> std::io::Result<()>;
+}
+
+/// A backend's lock, released when dropped.
+pub enum BackendLock {
+    File(FileLock),
+    Memory(MemoryLock),
+}
+
+/// The `.mt`/`.dt` file pair backing a storage on disk.
+pub struct FileBackend {
+    meta_store: MetaStore,
+    data_store: DataStore,
+    meta_store_path: PathBuf,
+}
+
+impl FileBackend {
+    /// Every sidecar name in use. Sidecars are files next to the `.mt` one,
+    /// with the name as extension, and move along with the storage.
+    pub const SIDECARS: &[&str] = &["snapshots", "journal", "index"];
+
+    pub fn open(meta_store_path: &Path, data_store_path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            meta_store: MetaStore::open(meta_store_path)?,
+            data_store: DataStore::open(data_store_path)?,
+            meta_store_path: meta_store_path.to_path_buf(),
+        })
+    }
+
+    pub fn sidecar_path(meta_store_path: &Path, name: &str) -> PathBuf {
+        meta_store_path.with_extension(name)
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn get_meta(&mut self) -> std::io::Result<Meta> {
+        self.meta_store.get()
+    }
+
+    fn update_meta(&mut self, meta: Meta) -> std::io::Result<()> {
+        self.meta_store.update(meta)
+    }
+
+    fn push(&mut self, record: &Record) -> std::io::Result<u64> {
+        self.data_store.push(record)
+    }
+
+    fn pick(&mut self, pointer: u64) -> std::io::Result<Record> {
+        self.data_store.pick(pointer)
+    }
+
+    fn get_all(&mut self) -> std::io::Result<Vec<Record>> {
+        self.data_store.get_all()
+    }
+
+    fn data_len(&mut self) -> std::io::Result<u64> {
+        self.data_store.data_len()
+    }
+
+    fn read_at(&mut self, pointer: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.data_store.read_at(pointer, len)
+    }
+
+    fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+        self.data_store.truncate(len)
+    }
+
+    fn set_active(&mut self, pointer: u64, is_active: bool) -> std::io::Result<()> {
+        self.data_store.set_active(pointer, is_active)
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.data_store.sync()?;
+        self.meta_store.sync()
+    }
+
+    fn lock(&mut self, kind: LockKind, timeout: Duration) -> std::io::Result<BackendLock> {
+        Ok(BackendLock::File(self.meta_store.lock(kind, timeout)?))
+    }
+
+    fn notifier(&self) -> Arc<Notifier> {
+        Notifier::for_path(&self.meta_store_path)
+    }
+
+    fn read_sidecar(&mut self, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+        match fs::read(Self::sidecar_path(&self.meta_store_path, name)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn sidecar_len(&mut self, name: &str) -> std::io::Result<Option<u64>> {
+        match fs::metadata(Self::sidecar_path(&self.meta_store_path, name)) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_sidecar_range(&mut self, name: &str, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut file = File::open(Self::sidecar_path(&self.meta_store_path, name))?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut content = Vec::new();
+        file.take(len as u64).read_to_end(&mut content)?;
+
+        Ok(content)
+    }
+
+    fn write_sidecar(&mut self, name: &str, content: &[u8]) -> std::io::Result<()> {
+        let path = Self::sidecar_path(&self.meta_store_path, name);
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+
+        fs::rename(temp_path, path)
+    }
+
+    fn append_sidecar(&mut self, name: &str, content: &[u8]) -> std::io::Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::sidecar_path(&self.meta_store_path, name))?
+            .write_all(content)
+    }
+}
@@ -0,0 +1,162 @@
+//! Archive holding the config and every storage's files, for backups.
+//!
+//! The archive starts with a text manifest, one line per file with its size,
+//! CRC-32 and path, closed by an `end` line. The file contents follow in
+//! manifest order:
+//!
+//! ```text
+//! re-queue backup 1
+//! file 120 1c291ca3 config
+//! file 32 7f1a8b20 storage/inbox.mt
+//! file 163 0b3c44de storage/inbox.dt
+//! end
+//! <config><inbox.mt><inbox.dt>
+//! ```
+
+use std::{
+    fmt,
+    io::{BufRead, Read, Write},
+};
+
+use crate::checksum;
+
+const HEADER: &str = "re-queue backup 1";
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(std::io::Error),
+    InvalidArchive(String),
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "i/o error: {e}"),
+            BackupError::InvalidArchive(message) => write!(f, "invalid backup archive: {message}"),
+        }
+    }
+}
+
+/// A file in the archive, by its path relative to the re-queue home.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveFile {
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+pub fn write_archive(out: &mut impl Write, files: &[ArchiveFile]) -> std::io::Result<()> {
+    writeln!(out, "{HEADER}")?;
+    for file in files {
+        writeln!(out, "file {} {:08x} {}", file.content.len(), checksum::crc32(&file.content), file.path)?;
+    }
+    writeln!(out, "end")?;
+
+    for file in files {
+        out.write_all(&file.content)?;
+    }
+
+    out.flush()
+}
+
+/// Reads a whole archive, checking every file against its checksum.
+pub fn read_archive(input: &mut impl BufRead) -> Result<Vec<ArchiveFile>, BackupError> {
+    let invalid = |message: String| BackupError::InvalidArchive(message);
+
+    if read_manifest_line(input)? != HEADER {
+        return Err(invalid("not a re-queue backup".to_string()));
+    }
+
+    let mut manifest = Vec::new();
+    loop {
+        let line = read_manifest_line(input)?;
+        if line == "end" {
+            break;
+        }
+
+        let mut fields = line.splitn(4, ' ');
+        let entry = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some("file"), Some(size), Some(checksum), Some(path)) => size
+                .parse::<u64>()
+                .ok()
+                .zip(u32::from_str_radix(checksum, 16).ok())
+                .map(|(size, checksum)| (path.to_string(), size, checksum)),
+            _ => None,
+        };
+
+        manifest.push(entry.ok_or_else(|| invalid(format!("bad manifest line '{line}'")))?);
+    }
+
+    let mut files = Vec::with_capacity(manifest.len());
+    for (path, size, expected_checksum) in manifest {
+        let mut content = Vec::new();
+        input.take(size).read_to_end(&mut content)?;
+
+        if content.len() as u64 != size {
+            return Err(invalid(format!("{path} is cut short")));
+        }
+        if checksum::crc32(&content) != expected_checksum {
+            return Err(invalid(format!("{path} fails its checksum")));
+        }
+
+        files.push(ArchiveFile { path, content });
+    }
+
+    if input.read(&mut [0])? != 0 {
+        return Err(invalid("unexpected data after the last file".to_string()));
+    }
+
+    Ok(files)
+}
+
+fn read_manifest_line(input: &mut impl BufRead) -> Result<String, BackupError> {
+    let mut line = String::new();
+    input.read_line(&mut line).map_err(|_| BackupError::InvalidArchive("manifest is not text".to_string()))?;
+
+    if !line.ends_with('\n') {
+        return Err(BackupError::InvalidArchive("manifest is cut short".to_string()));
+    }
+
+    line.pop();
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive() -> Vec<u8> {
+        let files = [
+            ArchiveFile { path: "config".to_string(), content: b"storage_list = [\"a b\"]\n".to_vec() },
+            ArchiveFile { path: "storage/a b.dt".to_string(), content: vec![0, 1, 2, 255] },
+        ];
+
+        let mut out = Vec::new();
+        write_archive(&mut out, &files).unwrap();
+        out
+    }
+
+    #[test]
+    fn archive_round_trips() {
+        let files = read_archive(&mut archive().as_slice()).unwrap();
+
+        assert_eq!(files[1].path, "storage/a b.dt");
+        assert_eq!(files[1].content, [0, 1, 2, 255]);
+    }
+
+    #[test]
+    fn damage_is_detected() {
+        let mut damaged = archive();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 1;
+        assert!(read_archive(&mut damaged.as_slice()).unwrap_err().to_string().contains("checksum"));
+
+        let cut = archive();
+        assert!(read_archive(&mut &cut[..cut.len() - 1]).is_err());
+    }
+}
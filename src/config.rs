@@ -1,13 +1,106 @@
+use std::{collections::HashMap, fmt};
+
+use crate::durability::Durability;
+
 #[derive(Debug, PartialEq)]
 pub enum ConfigError {
     StorageNotFound,
     StorageAlreadyExists,
+    InvalidRotation,
+    InvalidStorageName(InvalidStorageName),
+}
+
+impl From<InvalidStorageName> for ConfigError {
+    fn from(e: InvalidStorageName) -> Self {
+        ConfigError::InvalidStorageName(e)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::StorageNotFound => write!(f, "storage not found"),
+            ConfigError::StorageAlreadyExists => write!(f, "storage already exists"),
+            ConfigError::InvalidRotation => write!(f, "rotation needs distinct storages with weights above zero"),
+            ConfigError::InvalidStorageName(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Why a name can't be used for a storage. Names end up in file names, so
+/// anything that could leave the storage directory or be mangled by the
+/// file system is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidStorageName {
+    Empty,
+    TooLong,
+    PathSeparator,
+    ControlCharacter,
+    LeadingDot,
+    SurroundingWhitespace,
+}
+
+impl fmt::Display for InvalidStorageName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidStorageName::Empty => write!(f, "storage name is empty"),
+            InvalidStorageName::TooLong => write!(f, "storage name is longer than {MAX_STORAGE_NAME_LEN} bytes"),
+            InvalidStorageName::PathSeparator => write!(f, "storage name contains '/' or '\\'"),
+            InvalidStorageName::ControlCharacter => write!(f, "storage name contains control characters"),
+            InvalidStorageName::LeadingDot => write!(f, "storage name starts with '.'"),
+            InvalidStorageName::SurroundingWhitespace => write!(f, "storage name starts or ends with whitespace"),
+        }
+    }
+}
+
+/// Leaves room for the extension within the usual 255 byte file name limit.
+pub const MAX_STORAGE_NAME_LEN: usize = 128;
+
+pub fn validate_storage_name(storage_name: &str) -> Result<(), InvalidStorageName> {
+    if storage_name.is_empty() {
+        return Err(InvalidStorageName::Empty);
+    }
+    if storage_name.len() > MAX_STORAGE_NAME_LEN {
+        return Err(InvalidStorageName::TooLong);
+    }
+    if storage_name.contains(['/', '\\']) {
+        return Err(InvalidStorageName::PathSeparator);
+    }
+    if storage_name.chars().any(char::is_control) {
+        return Err(InvalidStorageName::ControlCharacter);
+    }
+    if storage_name.starts_with('.') {
+        return Err(InvalidStorageName::LeadingDot);
+    }
+    if storage_name.trim() != storage_name {
+        return Err(InvalidStorageName::SurroundingWhitespace);
+    }
+
+    Ok(())
+}
+
+/// A storage taking part in round-robin, and how many records are taken from
+/// it before moving on to the next one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationEntry {
+    pub storage_name: String,
+    pub weight: u32,
+}
+
+/// Settings applied to a storage whenever it's opened.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StorageSettings {
+    pub durability: Durability,
+    /// Upper bound on active records; saves beyond it are refused.
+    pub max_records: Option<u64>,
 }
 
 #[derive(Debug)]
 pub struct Config {
     active_storage: String,
     storage_list: Vec<String>,
+    rotation: Vec<RotationEntry>,
+    storage_settings: HashMap<String, StorageSettings>,
 }
 
 impl Config {
@@ -15,6 +108,8 @@ impl Config {
         Self {
             active_storage: String::from(""),
             storage_list: Vec::new(),
+            rotation: Vec::new(),
+            storage_settings: HashMap::new(),
         }
     }
 
@@ -37,6 +132,14 @@ impl Config {
     }
 
     pub fn add_storage(&mut self, storage_name: &str) -> Result<(), ConfigError> {
+        validate_storage_name(storage_name)?;
+        self.add_existing_storage(storage_name)
+    }
+
+    /// Adds a storage read from a config on disk. The name isn't validated,
+    /// so storages named before the rules came in stay reachable;
+    /// `invalid_storage_names` lists them.
+    pub fn add_existing_storage(&mut self, storage_name: &str) -> Result<(), ConfigError> {
         if self.storage_list.contains(&storage_name.to_string()) {
             return Err(ConfigError::StorageAlreadyExists);
         }
@@ -46,14 +149,120 @@ impl Config {
         Ok(())
     }
 
+    /// Removes a storage, clearing the active one if it was that storage.
+    pub fn remove_storage(&mut self, storage_name: &str) -> Result<(), ConfigError> {
+        let index = self
+            .storage_list
+            .iter()
+            .position(|s| s == storage_name)
+            .ok_or(ConfigError::StorageNotFound)?;
+
+        self.storage_list.remove(index);
+        self.rotation.retain(|entry| entry.storage_name != storage_name);
+        self.storage_settings.remove(storage_name);
+
+        if self.active_storage == storage_name {
+            self.active_storage.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Renames a storage in place, keeping it active if it was.
+    pub fn rename_storage(&mut self, storage_name: &str, new_storage_name: &str) -> Result<(), ConfigError> {
+        validate_storage_name(new_storage_name)?;
+        if self.storage_list.iter().any(|s| s == new_storage_name) {
+            return Err(ConfigError::StorageAlreadyExists);
+        }
+
+        let storage = self
+            .storage_list
+            .iter_mut()
+            .find(|s| *s == storage_name)
+            .ok_or(ConfigError::StorageNotFound)?;
+
+        *storage = new_storage_name.to_string();
+
+        for entry in self.rotation.iter_mut().filter(|entry| entry.storage_name == storage_name) {
+            entry.storage_name = new_storage_name.to_string();
+        }
+
+        if let Some(settings) = self.storage_settings.remove(storage_name) {
+            self.storage_settings.insert(new_storage_name.to_string(), settings);
+        }
+
+        if self.active_storage == storage_name {
+            self.active_storage = new_storage_name.to_string();
+        }
+
+        Ok(())
+    }
+
+    pub fn has_storage(&self, storage_name: &str) -> bool {
+        self.storage_list.iter().any(|s| s == storage_name)
+    }
+
+    /// Like `add_existing_storage`, for a whole list.
     pub fn set_storage_list(&mut self, storage_list: Vec<String>) -> Result<(), ConfigError> {
         for storage in storage_list {
-            self.add_storage(&storage)?;
+            self.add_existing_storage(&storage)?;
+        }
+
+        Ok(())
+    }
+
+    /// Storages whose names are no longer allowed, and why. They keep
+    /// working, but should be renamed.
+    pub fn invalid_storage_names(&self) -> Vec<(String, InvalidStorageName)> {
+        self.storage_list
+            .iter()
+            .filter_map(|s| validate_storage_name(s).err().map(|e| (s.clone(), e)))
+            .collect()
+    }
+
+    /// Replaces the round-robin set. An empty set turns round-robin off.
+    pub fn set_rotation(&mut self, rotation: Vec<RotationEntry>) -> Result<(), ConfigError> {
+        for (index, entry) in rotation.iter().enumerate() {
+            if !self.has_storage(&entry.storage_name) {
+                return Err(ConfigError::StorageNotFound);
+            }
+
+            let is_duplicate = rotation[..index]
+                .iter()
+                .any(|other| other.storage_name == entry.storage_name);
+
+            if entry.weight == 0 || is_duplicate {
+                return Err(ConfigError::InvalidRotation);
+            }
         }
 
+        self.rotation = rotation;
+
         Ok(())
     }
 
+    pub fn get_rotation(&self) -> &[RotationEntry] {
+        &self.rotation
+    }
+
+    pub fn set_storage_settings(&mut self, storage_name: &str, settings: StorageSettings) -> Result<(), ConfigError> {
+        if !self.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound);
+        }
+
+        if settings == StorageSettings::default() {
+            self.storage_settings.remove(storage_name);
+        } else {
+            self.storage_settings.insert(storage_name.to_string(), settings);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_storage_settings(&self, storage_name: &str) -> StorageSettings {
+        self.storage_settings.get(storage_name).copied().unwrap_or_default()
+    }
+
     pub fn get_storage_list(&self) -> &[String] {
         &self.storage_list
     }
@@ -63,6 +272,12 @@ impl Config {
     }
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,6 +298,29 @@ mod tests {
         assert_eq!(config.get_storage_list(), &["test".to_string()]);
     }
 
+    #[test]
+    fn add_storage_rejects_unsafe_names() {
+        let mut config = Config::new();
+
+        for (storage_name, error) in [
+            ("", InvalidStorageName::Empty),
+            ("../outside", InvalidStorageName::PathSeparator),
+            ("a\\b", InvalidStorageName::PathSeparator),
+            (".hidden", InvalidStorageName::LeadingDot),
+            ("trailing ", InvalidStorageName::SurroundingWhitespace),
+            ("new\nline", InvalidStorageName::ControlCharacter),
+        ] {
+            assert_eq!(config.add_storage(storage_name), Err(ConfigError::InvalidStorageName(error)));
+        }
+
+        assert_eq!(
+            config.add_storage(&"x".repeat(MAX_STORAGE_NAME_LEN + 1)),
+            Err(ConfigError::InvalidStorageName(InvalidStorageName::TooLong))
+        );
+        assert!(config.add_storage("with spaces, \"quotes\" and ünïcode").is_ok());
+        assert!(!config.has_storage(""));
+    }
+
     #[test]
     fn add_storage_duplicate_fails() {
         let mut config = Config::new();
@@ -112,6 +350,72 @@ mod tests {
         assert_eq!(config.get_active_storage(), Some("test".to_string()));
     }
 
+    #[test]
+    fn remove_active_storage_clears_active() {
+        let mut config = Config::new();
+        config.add_storage("test").unwrap();
+        config.set_active_storage("test").unwrap();
+        config.remove_storage("test").unwrap();
+
+        assert!(!config.has_storages());
+        assert_eq!(config.get_active_storage(), None);
+    }
+
+    #[test]
+    fn rename_storage_keeps_active() {
+        let mut config = Config::new();
+        config.add_storage("test").unwrap();
+        config.set_active_storage("test").unwrap();
+        config.rename_storage("test", "renamed").unwrap();
+
+        assert_eq!(config.get_storage_list(), &["renamed".to_string()]);
+        assert_eq!(config.get_active_storage(), Some("renamed".to_string()));
+    }
+
+    #[test]
+    fn rename_storage_to_existing_name_fails() {
+        let mut config = Config::new();
+        config.add_storage("first").unwrap();
+        config.add_storage("second").unwrap();
+
+        assert_eq!(
+            config.rename_storage("first", "second"),
+            Err(ConfigError::StorageAlreadyExists)
+        );
+    }
+
+    #[test]
+    fn set_rotation_validates_entries() {
+        let mut config = Config::new();
+        config.add_storage("test").unwrap();
+
+        let entry = |storage_name: &str, weight| RotationEntry { storage_name: storage_name.to_string(), weight };
+
+        assert_eq!(config.set_rotation(vec![entry("missing", 1)]), Err(ConfigError::StorageNotFound));
+        assert_eq!(config.set_rotation(vec![entry("test", 0)]), Err(ConfigError::InvalidRotation));
+        assert_eq!(
+            config.set_rotation(vec![entry("test", 1), entry("test", 2)]),
+            Err(ConfigError::InvalidRotation)
+        );
+        assert!(config.set_rotation(vec![entry("test", 2)]).is_ok());
+
+        config.remove_storage("test").unwrap();
+        assert!(config.get_rotation().is_empty());
+    }
+
+    #[test]
+    fn storage_settings_follow_renames() {
+        let mut config = Config::new();
+        config.add_storage("test").unwrap();
+
+        let settings = StorageSettings { durability: Durability::Never, max_records: Some(10) };
+        config.set_storage_settings("test", settings).unwrap();
+        config.rename_storage("test", "renamed").unwrap();
+
+        assert_eq!(config.get_storage_settings("renamed"), settings);
+        assert_eq!(config.get_storage_settings("test"), StorageSettings::default());
+    }
+
     #[test]
     fn set_storage_list_rejects_duplicates() {
         let mut config = Config::new();
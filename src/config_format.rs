@@ -0,0 +1,427 @@
+//! Reader and writer helpers for the config file format, a small subset of
+//! TOML: `key = value` pairs grouped under `[table]` headers, with strings,
+//! integers, booleans and arrays as values.
+
+use std::{fmt, iter::Peekable, str::Chars};
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(position: Position, message: impl Into<String>) -> Self {
+        Self {
+            line: position.line,
+            column: position.column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<(Value, Position)>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub key_position: Position,
+    pub value: Value,
+    pub value_position: Position,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Table {
+    /// Dotted header path, empty for the entries before the first header.
+    pub path: Vec<String>,
+    pub position: Position,
+    pub entries: Vec<Entry>,
+}
+
+/// Parses a whole document. The first table is always the root one.
+pub fn parse(input: &str) -> Result<Vec<Table>, ParseError> {
+    Parser::new(input).parse()
+}
+
+/// Writes `value` as a quoted string, escaping what needs escaping.
+pub fn quote(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+/// Writes `key` bare when that's allowed, quoted otherwise.
+pub fn key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(is_bare_key_char) {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    position: Position,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            position: Position { line: 1, column: 1 },
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<Table>, ParseError> {
+        let mut tables = vec![Table {
+            path: Vec::new(),
+            position: self.position,
+            entries: Vec::new(),
+        }];
+
+        loop {
+            self.skip_whitespace_and_newlines();
+
+            match self.chars.peek() {
+                None => break,
+                Some('[') => {
+                    let position = self.position;
+                    self.next();
+                    self.skip_whitespace();
+                    let path = self.parse_key_path()?;
+                    self.expect(']')?;
+                    self.expect_line_end()?;
+
+                    if tables.iter().any(|t| t.path == path) {
+                        return Err(ParseError::new(position, "duplicate table"));
+                    }
+
+                    tables.push(Table { path, position, entries: Vec::new() });
+                }
+                Some(_) => {
+                    let key_position = self.position;
+                    let key = self.parse_key()?;
+                    self.skip_whitespace();
+                    self.expect('=')?;
+                    self.skip_whitespace();
+                    let value_position = self.position;
+                    let value = self.parse_value()?;
+                    self.expect_line_end()?;
+
+                    let table = tables.last_mut().unwrap();
+                    if table.entries.iter().any(|e| e.key == key) {
+                        return Err(ParseError::new(key_position, format!("duplicate key '{key}'")));
+                    }
+
+                    table.entries.push(Entry { key, key_position, value, value_position });
+                }
+            }
+        }
+
+        Ok(tables)
+    }
+
+    fn parse_key_path(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = vec![self.parse_key()?];
+        self.skip_whitespace();
+
+        while self.chars.peek() == Some(&'.') {
+            self.next();
+            self.skip_whitespace();
+            path.push(self.parse_key()?);
+            self.skip_whitespace();
+        }
+
+        Ok(path)
+    }
+
+    fn parse_key(&mut self) -> Result<String, ParseError> {
+        if self.chars.peek() == Some(&'"') {
+            return self.parse_string();
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !is_bare_key_char(c) {
+                break;
+            }
+            key.push(c);
+            self.next();
+        }
+
+        if key.is_empty() {
+            return Err(self.error("expected a key"));
+        }
+
+        Ok(key)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.chars.peek() {
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some('[') => self.parse_array(),
+            Some(c) if c.is_ascii_digit() || *c == '-' || *c == '+' => self.parse_integer(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let position = self.position;
+                let word = self.parse_key()?;
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => Err(ParseError::new(position, format!("unexpected '{word}', strings must be quoted"))),
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut result = String::new();
+
+        loop {
+            let position = self.position;
+            match self.next() {
+                None | Some('\n') => return Err(ParseError::new(position, "unterminated string")),
+                Some('"') => return Ok(result),
+                Some('\\') => match self.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('u') => result.push(self.parse_unicode_escape(4, position)?),
+                    Some('U') => result.push(self.parse_unicode_escape(8, position)?),
+                    _ => return Err(ParseError::new(position, "invalid escape sequence")),
+                },
+                Some(c) if c.is_control() && c != '\t' => {
+                    return Err(ParseError::new(position, "control characters must be escaped"));
+                }
+                Some(c) => result.push(c),
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self, digits: usize, position: Position) -> Result<char, ParseError> {
+        let mut code = 0u32;
+        for _ in 0..digits {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| ParseError::new(position, "invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+
+        char::from_u32(code).ok_or_else(|| ParseError::new(position, "invalid unicode escape"))
+    }
+
+    fn parse_integer(&mut self) -> Result<Value, ParseError> {
+        let position = self.position;
+        let mut digits = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '_') {
+                break;
+            }
+            if c != '_' {
+                digits.push(c);
+            }
+            self.next();
+        }
+
+        digits
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| ParseError::new(position, format!("invalid integer '{digits}'")))
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_newlines();
+            if self.chars.peek() == Some(&']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+
+            let position = self.position;
+            items.push((self.parse_value()?, position));
+            self.skip_whitespace_and_newlines();
+
+            match self.chars.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some(']') => {}
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.chars.peek() {
+            Some(&c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            _ => Err(self.error(format!("expected '{expected}'"))),
+        }
+    }
+
+    /// Only whitespace or a comment may follow a value or a header.
+    fn expect_line_end(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        self.skip_comment();
+
+        match self.chars.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') => {
+                self.next();
+                self.expect('\n')
+            }
+            Some(_) => Err(self.error("expected end of line")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t') = self.chars.peek() {
+            self.next();
+        }
+    }
+
+    fn skip_whitespace_and_newlines(&mut self) {
+        loop {
+            match self.chars.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.next();
+                }
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.chars.peek() == Some(&'#') {
+            while let Some(&c) = self.chars.peek() {
+                if c == '\n' {
+                    break;
+                }
+                self.next();
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn error(&mut self, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.position, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_and_values() {
+        let tables = parse(
+            "name = \"a\" # comment\n\
+             list = [\n  \"x\",\n  \"y\",\n]\n\
+             \n\
+             [storages.\"q, \\\"1\\\"\"]\n\
+             limit = 10\n\
+             enabled = true\n",
+        )
+        .unwrap();
+
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].entries[0].value, Value::String("a".to_string()));
+        assert!(matches!(&tables[0].entries[1].value, Value::Array(items) if items.len() == 2));
+        assert_eq!(tables[1].path, ["storages", "q, \"1\""]);
+        assert_eq!(tables[1].entries[0].value, Value::Integer(10));
+        assert_eq!(tables[1].entries[1].value, Value::Boolean(true));
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        let original = "tab\t \"quote\" back\\slash \u{1}";
+        let tables = parse(&format!("key = {}", quote(original))).unwrap();
+
+        assert_eq!(tables[0].entries[0].value, Value::String(original.to_string()));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(
+            parse("a = 1\nb = oops\n").unwrap_err(),
+            ParseError {
+                line: 2,
+                column: 5,
+                message: "unexpected 'oops', strings must be quoted".to_string()
+            }
+        );
+        assert_eq!(parse("a = 1\na = 2").unwrap_err().line, 2);
+        assert_eq!(parse("a = \"open").unwrap_err().message, "unterminated string");
+    }
+}
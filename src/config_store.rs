@@ -1,40 +1,132 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{ErrorKind, Read, Seek, Write},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
 };
 
-use crate::config::Config;
+use crate::{
+    config::{Config, ConfigError, RotationEntry, StorageSettings},
+    config_format::{self, Entry, ParseError, Position, Value},
+    file_lock::{FileLock, LockKind},
+};
 
 #[derive(Debug)]
 pub enum ConfigStoreError {
     Io(std::io::Error),
     InvalidFormat,
+    Parse(ParseError),
+    Locked,
 }
 
 impl From<std::io::Error> for ConfigStoreError {
     fn from(e: std::io::Error) -> Self {
-        ConfigStoreError::Io(e)
+        if FileLock::is_contention(&e) {
+            ConfigStoreError::Locked
+        } else {
+            ConfigStoreError::Io(e)
+        }
+    }
+}
+
+impl From<ParseError> for ConfigStoreError {
+    fn from(e: ParseError) -> Self {
+        ConfigStoreError::Parse(e)
+    }
+}
+
+impl fmt::Display for ConfigStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigStoreError::Io(e) => write!(f, "config i/o error: {e}"),
+            ConfigStoreError::InvalidFormat => write!(f, "config has invalid format"),
+            ConfigStoreError::Parse(e) => write!(f, "config is invalid: {e}"),
+            ConfigStoreError::Locked => write!(f, "config is in use by another process"),
+        }
     }
 }
 
 pub struct ConfigStore {}
 
 impl ConfigStore {
-    pub fn load(path: &str) -> Result<Config, ConfigStoreError> {
+    /// Reads the config, creating a default one if there is none. A config
+    /// that's missing or unreadable while its backup is fine is replaced by
+    /// the backup.
+    pub fn load(path: &Path) -> Result<Config, ConfigStoreError> {
+        {
+            let _lock = Self::lock(path, LockKind::Shared)?;
+
+            match Self::read(path) {
+                Ok(Some(config)) => return Ok(config),
+                Ok(None) => {}
+                Err(e) => return Self::read(&Self::backup_path(path)).ok().flatten().ok_or(e),
+            }
+        }
+
+        let _lock = Self::lock(path, LockKind::Exclusive)?;
+
+        // Another process may have written it while the lock was released
+        if let Ok(Some(config)) = Self::read(path) {
+            return Ok(config);
+        }
+        if let Ok(Some(config)) = Self::read(&Self::backup_path(path)) {
+            return Ok(config);
+        }
+
+        let config = Config::new();
+        Self::write_atomic(path, &Self::serialize(&config))?;
+
+        Ok(config)
+    }
+
+    /// Replaces the config atomically: the new content goes to a temporary
+    /// file that is synced and then renamed over the config, so a crash
+    /// leaves either the old or the new version. The old version is kept as
+    /// a backup for `load` to fall back to.
+    pub fn persist(path: &Path, config: &Config) -> Result<(), ConfigStoreError> {
+        let _lock = Self::lock(path, LockKind::Exclusive)?;
+
+        Self::persist_locked(path, config)
+    }
+
+    /// Applies `change` to the config as it is on disk and persists the
+    /// result, all under one lock, so changes other processes made since this
+    /// one loaded the config aren't overwritten. Returns the new config.
+    pub fn update<T, E: From<ConfigStoreError>>(
+        path: &Path,
+        change: impl FnOnce(&mut Config) -> Result<T, E>,
+    ) -> Result<(Config, T), E> {
+        let _lock = Self::lock(path, LockKind::Exclusive)?;
+
+        let mut config = match Self::read(path) {
+            Ok(Some(config)) => config,
+            Ok(None) => Self::read(&Self::backup_path(path))?.unwrap_or_else(Config::new),
+            Err(e) => Self::read(&Self::backup_path(path)).ok().flatten().ok_or(e)?,
+        };
+
+        let result = change(&mut config)?;
+        Self::persist_locked(path, &config)?;
+
+        Ok((config, result))
+    }
+
+    fn persist_locked(path: &Path, config: &Config) -> Result<(), ConfigStoreError> {
+        if let Ok(Some(_)) = Self::read(path) {
+            let previous = fs::read(path)?;
+            Self::write_atomic(&Self::backup_path(path), &String::from_utf8_lossy(&previous))?;
+        }
+
+        Self::write_atomic(path, &Self::serialize(config))
+    }
+
+    /// `Ok(None)` when there's no file at `path`.
+    fn read(path: &Path) -> Result<Option<Config>, ConfigStoreError> {
         let mut file = match File::open(path) {
             Ok(file) => file,
-            Err(e) if e.kind() == ErrorKind::NotFound => {
-                let mut file = File::create_new(path)?;
-                let config = &Config::new();
-                file.write_all(Self::to_str(config).as_bytes())?;
-                file.sync_all()?;
-                file
-            }
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
             Err(e) => Err(e)?,
         };
 
-        file.seek(std::io::SeekFrom::Start(0))?;
-
         let mut content = String::new();
         file.read_to_string(&mut content)
             .map_err(|e| match e.kind() {
@@ -42,26 +134,249 @@ impl ConfigStore {
                 _ => e.into(),
             })?;
 
-        Ok(Self::from_str(&content)?)
+        Self::parse(&content).map(Some)
+    }
+
+    fn write_atomic(path: &Path, content: &str) -> Result<(), ConfigStoreError> {
+        let temp_path = Self::sibling_path(path, "tmp");
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_path, path)?;
+
+        // Persist the rename itself
+        if let Some(dir) = path.parent() {
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn persist(path: &str, config: &Config) -> Result<(), ConfigStoreError> {
-        let value = Self::to_str(config);
-        match OpenOptions::new()
-            .truncate(true)
+    /// The config is replaced by renames, so the lock lives in a file of its own.
+    fn lock(path: &Path, kind: LockKind) -> Result<FileLock, ConfigStoreError> {
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
             .write(true)
-            .open(path)
-        {
-            Ok(mut file) => {
-                file.write_all(value.as_bytes())?;
-                file.sync_all()?;
-                Ok(())
+            .open(Self::sibling_path(path, "lock"))?;
+
+        Ok(FileLock::acquire(&lock_file, kind, FileLock::DEFAULT_TIMEOUT)?)
+    }
+
+    fn backup_path(path: &Path) -> PathBuf {
+        Self::sibling_path(path, "bak")
+    }
+
+    /// `path` with `suffix` appended, e.g. `config.bak` for `config`.
+    fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".");
+        path.push(suffix);
+
+        PathBuf::from(path)
+    }
+
+    pub fn parse(content: &str) -> Result<Config, ConfigStoreError> {
+        if Self::is_legacy(content) {
+            return Self::from_legacy_str(content);
+        }
+
+        let tables = config_format::parse(content)?;
+        let mut config = Config::new();
+
+        let root = &tables[0];
+        let entry = |key: &str| root.entries.iter().find(|e| e.key == key);
+
+        for entry in &root.entries {
+            if !["active_storage", "storage_list", "rotation"].contains(&entry.key.as_str()) {
+                return Err(ParseError::new(entry.key_position, format!("unknown key '{}'", entry.key)).into());
             }
-            Err(e) => Err(e)?,
         }
+
+        // The list has to be in place before anything can refer to it
+        if let Some(entry) = entry("storage_list") {
+            for (storage_name, position) in Self::string_array(entry)? {
+                config
+                    .add_existing_storage(&storage_name)
+                    .map_err(|e| Self::config_error(position, e))?;
+            }
+        }
+
+        if let Some(entry) = entry("active_storage") {
+            let storage_name = Self::string(&entry.value, entry.value_position)?;
+            if !storage_name.is_empty() {
+                config
+                    .set_active_storage(&storage_name)
+                    .map_err(|e| Self::config_error(entry.value_position, e))?;
+            }
+        }
+
+        let mut rotation = Vec::new();
+        if let Some(entry) = entry("rotation") {
+            for (storage_name, position) in Self::string_array(entry)? {
+                if !config.has_storage(&storage_name) {
+                    return Err(Self::config_error(position, ConfigError::StorageNotFound));
+                }
+                rotation.push((RotationEntry { storage_name, weight: 1 }, position));
+            }
+        }
+
+        for table in &tables[1..] {
+            let storage_name = match table.path.as_slice() {
+                [section, storage_name] if section == "storages" => storage_name,
+                _ => return Err(ParseError::new(table.position, "unknown table, expected [storages.<name>]").into()),
+            };
+
+            if !config.has_storage(storage_name) {
+                return Err(Self::config_error(table.position, ConfigError::StorageNotFound));
+            }
+
+            let mut settings = StorageSettings::default();
+
+            for entry in &table.entries {
+                match entry.key.as_str() {
+                    "durability" => {
+                        settings.durability = Self::string(&entry.value, entry.value_position)?
+                            .parse()
+                            .map_err(|_| ParseError::new(
+                                entry.value_position,
+                                "durability must be always, never, every_ops:<n> or op_after_ms:<ms>",
+                            ))?;
+                    }
+                    "max_records" => {
+                        settings.max_records = Some(Self::positive_integer(entry)?);
+                    }
+                    "rotation_weight" => {
+                        let weight = Self::positive_integer(entry)?;
+                        let weight = u32::try_from(weight)
+                            .map_err(|_| ParseError::new(entry.value_position, "rotation_weight is too large"))?;
+
+                        match rotation.iter_mut().find(|(r, _)| &r.storage_name == storage_name) {
+                            Some((rotation_entry, _)) => rotation_entry.weight = weight,
+                            None => {
+                                return Err(ParseError::new(
+                                    entry.key_position,
+                                    "rotation_weight set for a storage that isn't in rotation",
+                                ).into());
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(ParseError::new(entry.key_position, format!("unknown key '{}'", entry.key)).into());
+                    }
+                }
+            }
+
+            config
+                .set_storage_settings(storage_name, settings)
+                .map_err(|e| Self::config_error(table.position, e))?;
+        }
+
+        let position = rotation.first().map(|(_, p)| *p).unwrap_or(root.position);
+        config
+            .set_rotation(rotation.into_iter().map(|(r, _)| r).collect())
+            .map_err(|e| Self::config_error(position, e))?;
+
+        Ok(config)
+    }
+
+    pub fn serialize(config: &Config) -> String {
+        let quoted_list = |items: Vec<&str>| {
+            items
+                .into_iter()
+                .map(config_format::quote)
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let mut result = String::new();
+        let active_storage = config.get_active_storage().unwrap_or_default();
+        let storage_list = config.get_storage_list().iter().map(String::as_str).collect();
+
+        result.push_str(&format!("active_storage = {}\n", config_format::quote(&active_storage)));
+        result.push_str(&format!("storage_list = [{}]\n", quoted_list(storage_list)));
+
+        if !config.get_rotation().is_empty() {
+            let rotation = config.get_rotation().iter().map(|r| r.storage_name.as_str()).collect();
+            result.push_str(&format!("rotation = [{}]\n", quoted_list(rotation)));
+        }
+
+        for storage_name in config.get_storage_list() {
+            let settings = config.get_storage_settings(storage_name);
+            let rotation_weight = config
+                .get_rotation()
+                .iter()
+                .find(|r| &r.storage_name == storage_name)
+                .map(|r| r.weight)
+                .filter(|weight| *weight != 1);
+
+            if settings == StorageSettings::default() && rotation_weight.is_none() {
+                continue;
+            }
+
+            result.push_str(&format!("\n[storages.{}]\n", config_format::key(storage_name)));
+
+            if settings.durability != StorageSettings::default().durability {
+                result.push_str(&format!("durability = {}\n", config_format::quote(&settings.durability.to_string())));
+            }
+            if let Some(max_records) = settings.max_records {
+                result.push_str(&format!("max_records = {max_records}\n"));
+            }
+            if let Some(weight) = rotation_weight {
+                result.push_str(&format!("rotation_weight = {weight}\n"));
+            }
+        }
+
+        result
+    }
+
+    fn string(value: &Value, position: Position) -> Result<String, ParseError> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            other => Err(ParseError::new(position, format!("expected a string, found {}", other.type_name()))),
+        }
+    }
+
+    fn string_array(entry: &Entry) -> Result<Vec<(String, Position)>, ParseError> {
+        match &entry.value {
+            Value::Array(items) => items
+                .iter()
+                .map(|(value, position)| Ok((Self::string(value, *position)?, *position)))
+                .collect(),
+            other => Err(ParseError::new(
+                entry.value_position,
+                format!("expected an array, found {}", other.type_name()),
+            )),
+        }
+    }
+
+    fn positive_integer(entry: &Entry) -> Result<u64, ParseError> {
+        match entry.value {
+            Value::Integer(n) if n > 0 => Ok(n as u64),
+            _ => Err(ParseError::new(entry.value_position, format!("{} must be a positive integer", entry.key))),
+        }
+    }
+
+    fn config_error(position: Position, e: ConfigError) -> ConfigStoreError {
+        ParseError::new(position, e.to_string()).into()
+    }
+
+    /// Configs written before the current format used `key: value` lines.
+    fn is_legacy(content: &str) -> bool {
+        content
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+            .is_some_and(|l| l.starts_with("active_storage:") || l.starts_with("storage_list:"))
     }
 
-    fn from_str(content: &str) -> Result<Config, ConfigStoreError> {
+    fn from_legacy_str(content: &str) -> Result<Config, ConfigStoreError> {
         let parts: Vec<&str> = content
             .lines()
             .map(str::trim)
@@ -72,7 +387,8 @@ impl ConfigStore {
             return Err(ConfigStoreError::InvalidFormat);
         }
 
-        let mut config = Config::new();
+        let mut active_storage = None;
+        let mut storage_list = None;
 
         for part in parts {
             let (key, value) = part
@@ -84,48 +400,157 @@ impl ConfigStore {
 
             match cleaned_key {
                 "active_storage" => {
-                    config.set_active_storage(value.trim().trim_matches('"'));
+                    active_storage = Some(value.trim().trim_matches('"').to_string());
                 }
                 "storage_list" => {
-                    let inner = value
-                        .trim()
-                        .strip_prefix('[')
-                        .and_then(|s| s.strip_suffix(']'))
-                        .ok_or(ConfigStoreError::InvalidFormat)?;
-
-                    let storage_list = if inner.is_empty() {
-                        Vec::new()
-                    } else {
-                        inner
-                            .split(',')
-                            .map(|s| s.trim().trim_matches('"').to_string())
-                            .collect()
-                    };
-
-                    config.set_storage_list(storage_list);
+                    storage_list = Some(Self::parse_legacy_list(value)?);
                 }
                 _ => return Err(ConfigStoreError::InvalidFormat),
             };
         }
 
+        let mut config = Config::new();
+
+        config
+            .set_storage_list(storage_list.ok_or(ConfigStoreError::InvalidFormat)?)
+            .map_err(|_| ConfigStoreError::InvalidFormat)?;
+
+        match active_storage {
+            Some(name) if !name.is_empty() => config
+                .set_active_storage(&name)
+                .map_err(|_| ConfigStoreError::InvalidFormat)?,
+            Some(_) => {}
+            None => return Err(ConfigStoreError::InvalidFormat),
+        }
+
         Ok(config)
     }
 
-    fn to_str(config: &Config) -> String {
-        let mut result = String::new();
-        let active_storage = match config.get_active_storage() {
-            Some(storage) => storage,
-            _ => String::new()
-        };
+    fn parse_legacy_list(value: &str) -> Result<Vec<String>, ConfigStoreError> {
+        let inner = value
+            .trim()
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or(ConfigStoreError::InvalidFormat)?;
 
-        result.push_str(&format!("active_storage: \"{}\"\n", active_storage));
-        let storage_list = config
-            .get_storage_list()
-            .iter()
-            .map(|s| format!("\"{s}\""))
-            .collect::<Vec<String>>()
-            .join(",");
-        result.push_str(&format!("storage_list: [{storage_list}]"));
-        result
+        Ok(if inner.is_empty() {
+            Vec::new()
+        } else {
+            inner
+                .split(',')
+                .map(|s| s.trim().trim_matches('"').to_string())
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{config::InvalidStorageName, durability::Durability};
+
+    use super::*;
+
+    #[test]
+    fn names_from_before_validation_still_load() {
+        let content = "active_storage = \".old\"\nstorage_list = [\".old\", \"inbox\"]\n";
+
+        let config = ConfigStore::parse(content).unwrap();
+        assert_eq!(config.get_active_storage(), Some(".old".to_string()));
+        assert_eq!(config.invalid_storage_names(), [(".old".to_string(), InvalidStorageName::LeadingDot)]);
+
+        let legacy = "active_storage: \"\"\nstorage_list: [\"a/b\"]\n";
+        let config = ConfigStore::parse(legacy).unwrap();
+        assert_eq!(config.invalid_storage_names(), [("a/b".to_string(), InvalidStorageName::PathSeparator)]);
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let mut config = Config::new();
+        for storage_name in ["inbox", "a, \"quoted\" name", "later"] {
+            config.add_storage(storage_name).unwrap();
+        }
+        config.set_active_storage("a, \"quoted\" name").unwrap();
+        config
+            .set_rotation(vec![
+                RotationEntry { storage_name: "inbox".to_string(), weight: 3 },
+                RotationEntry { storage_name: "later".to_string(), weight: 1 },
+            ])
+            .unwrap();
+        config
+            .set_storage_settings(
+                "inbox",
+                StorageSettings {
+                    durability: Durability::OnOpAfter(Duration::from_millis(500)),
+                    max_records: Some(100),
+                },
+            )
+            .unwrap();
+
+        let loaded = ConfigStore::parse(&ConfigStore::serialize(&config)).unwrap();
+
+        assert_eq!(loaded.get_storage_list(), config.get_storage_list());
+        assert_eq!(loaded.get_active_storage(), config.get_active_storage());
+        assert_eq!(loaded.get_rotation(), config.get_rotation());
+        assert_eq!(loaded.get_storage_settings("inbox"), config.get_storage_settings("inbox"));
+    }
+
+    fn temp_config_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("re-queue-config-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("config")
+    }
+
+    #[test]
+    fn persist_keeps_previous_version_as_backup() {
+        let path = temp_config_path("backup");
+        let mut config = ConfigStore::load(&path).unwrap();
+        config.add_storage("first").unwrap();
+        ConfigStore::persist(&path, &config).unwrap();
+        config.add_storage("second").unwrap();
+        ConfigStore::persist(&path, &config).unwrap();
+
+        let backup = ConfigStore::read(&ConfigStore::backup_path(&path)).unwrap().unwrap();
+        assert_eq!(backup.get_storage_list(), ["first"]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn load_falls_back_to_backup() {
+        let path = temp_config_path("fallback");
+        let mut config = ConfigStore::load(&path).unwrap();
+        config.add_storage("first").unwrap();
+        ConfigStore::persist(&path, &config).unwrap();
+        ConfigStore::persist(&path, &config).unwrap();
+
+        // What a crash halfway through the old truncate-and-write left behind
+        fs::write(&path, "storage_list = [\"fir").unwrap();
+        assert_eq!(ConfigStore::load(&path).unwrap().get_storage_list(), ["first"]);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ConfigStore::load(&path).unwrap().get_storage_list(), ["first"]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn legacy_config_is_still_read() {
+        let config = ConfigStore::parse("active_storage: \"b\"\nstorage_list: [\"a\",\"b\"]").unwrap();
+
+        assert_eq!(config.get_storage_list(), ["a", "b"]);
+        assert_eq!(config.get_active_storage(), Some("b".to_string()));
+    }
+
+    #[test]
+    fn unknown_active_storage_reports_position() {
+        let result = ConfigStore::parse("storage_list = [\"a\"]\nactive_storage = \"b\"\n");
+
+        match result {
+            Err(ConfigStoreError::Parse(e)) => assert_eq!((e.line, e.column), (2, 18)),
+            other => panic!("unexpected result: {other:?}"),
+        }
     }
 }
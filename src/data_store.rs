@@ -1,4 +1,4 @@
-use std::{fs::{File, OpenOptions}, io::{ErrorKind, Read, Seek, Write}};
+use std::{fs::{File, OpenOptions}, io::{ErrorKind, Read, Seek, Write}, path::Path};
 
 use crate::{record::Record, record_header::RecordHeader};
 
@@ -7,7 +7,7 @@ pub struct DataStore {
 }
 
 impl DataStore {
-    pub fn open(path: &str) -> std::io::Result<Self> {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
         match OpenOptions::new().read(true).write(true).open(path) {
             Ok(file) => Ok(Self { file }),
             Err(e) if e.kind() == ErrorKind::NotFound => {
@@ -23,11 +23,20 @@ impl DataStore {
         let bytes = record.to_bytes();
         self.file.seek(std::io::SeekFrom::End(0))?;
         self.file.write_all(&bytes)?;
-        self.file.sync_data()?;
 
         Ok(bytes.len() as u64)
     }
 
+    /// Flips the active flag of the record starting at `pointer` in place.
+    pub fn set_active(&mut self, pointer: u64, is_active: bool) -> std::io::Result<()> {
+        self.file.seek(std::io::SeekFrom::Start(pointer + RecordHeader::ACTIVE_FLAG_OFFSET))?;
+        self.file.write_all(&[is_active as u8])
+    }
+
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.file.sync_data()
+    }
+
     pub fn pick(&mut self, pointer: u64) -> std::io::Result<Record> {
         self.file.seek(std::io::SeekFrom::Start(pointer))?;
 
@@ -35,27 +44,40 @@ impl DataStore {
         self.file.read_exact(&mut record_header_buffer)?;
 
         let record_header = RecordHeader::from_bytes(&record_header_buffer);
+        record_header.record_end(pointer, self.data_len()?)?;
 
-        let mut data_buffer = Vec::<u8>::with_capacity(record_header.get_content_size() as usize);
-        data_buffer.resize(record_header.get_content_size() as usize, 0);
+        let mut data_buffer = vec![0u8; record_header.get_content_size() as usize];
         self.file.read_exact(&mut data_buffer)?;
 
-        Ok(Record::from_bytes(record_header, &data_buffer))
+        Record::from_bytes(record_header, &data_buffer)
+    }
+
+    pub fn data_len(&mut self) -> std::io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub fn read_at(&mut self, pointer: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.file.seek(std::io::SeekFrom::Start(pointer))?;
+
+        let mut buffer = vec![0u8; len];
+        self.file.read_exact(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    /// Cuts the file down to `len` bytes, dropping whatever follows.
+    pub fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+        self.file.set_len(len)
     }
 
     pub fn get_all(&mut self) -> std::io::Result<Vec<Record>> {
         let mut result = Vec::<Record>::new();
         let mut pointer = 0u64;
 
-        loop {
-            match self.pick(pointer) {
-                Ok(record) => {
-                    pointer += record.size();
-                    result.push(record);
-                },
-                // TODO: add correct error handling
-                Err(_) => break,
-            }
+        // TODO: add correct error handling
+        while let Ok(record) = self.pick(pointer) {
+            pointer += record.size();
+            result.push(record);
         }
 
         Ok(result)
@@ -0,0 +1,139 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// How often a storage forces its files to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Sync after every mutating operation.
+    #[default]
+    Always,
+    /// Sync once every N mutating operations.
+    EveryOps(u64),
+    /// Sync on the first mutating operation once the given time has passed
+    /// since the last sync. No timer runs: writes followed by a quiet spell
+    /// stay unsynced until a later operation, a `flush`, or the storage
+    /// closing.
+    OnOpAfter(Duration),
+    /// Leave syncing to the OS, or to an explicit `flush`.
+    Never,
+}
+
+/// Written as `always`, `never`, `every_ops:<n>` or `op_after_ms:<ms>`.
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::Always => write!(f, "always"),
+            Durability::EveryOps(n) => write!(f, "every_ops:{n}"),
+            Durability::OnOpAfter(delay) => write!(f, "op_after_ms:{}", delay.as_millis()),
+            Durability::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for Durability {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "always" => Ok(Durability::Always),
+            None if s == "never" => Ok(Durability::Never),
+            Some(("every_ops", n)) => n.parse().map(Durability::EveryOps).map_err(|_| ()),
+            Some(("op_after_ms", ms)) => {
+                ms.parse().map(|ms| Durability::OnOpAfter(Duration::from_millis(ms))).map_err(|_| ())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+/// Tracks unsynced operations and decides when the policy asks for a sync.
+pub struct SyncTracker {
+    durability: Durability,
+    pending_ops: u64,
+    last_sync: Instant,
+}
+
+impl SyncTracker {
+    pub fn new(durability: Durability) -> Self {
+        Self {
+            durability,
+            pending_ops: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    /// Registers a mutating operation and returns whether a sync is due.
+    pub fn record_op(&mut self) -> bool {
+        self.pending_ops += 1;
+
+        match self.durability {
+            Durability::Always => true,
+            Durability::EveryOps(n) => self.pending_ops >= n.max(1),
+            Durability::OnOpAfter(delay) => self.last_sync.elapsed() >= delay,
+            Durability::Never => false,
+        }
+    }
+
+    pub fn mark_synced(&mut self) {
+        self.pending_ops = 0;
+        self.last_sync = Instant::now();
+    }
+
+    pub fn has_pending(&self) -> bool { self.pending_ops > 0 }
+
+    pub fn get_durability(&self) -> Durability { self.durability }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn always_syncs_every_op() {
+        let mut tracker = SyncTracker::new(Durability::Always);
+
+        assert!(tracker.record_op());
+        tracker.mark_synced();
+        assert!(tracker.record_op());
+    }
+
+    #[test]
+    fn every_ops_groups_operations() {
+        let mut tracker = SyncTracker::new(Durability::EveryOps(3));
+
+        assert!(!tracker.record_op());
+        assert!(!tracker.record_op());
+        assert!(tracker.record_op());
+        tracker.mark_synced();
+        assert!(!tracker.has_pending());
+    }
+
+    #[test]
+    fn durability_round_trips_through_strings() {
+        for durability in [
+            Durability::Always,
+            Durability::EveryOps(10),
+            Durability::OnOpAfter(Duration::from_millis(250)),
+            Durability::Never,
+        ] {
+            assert_eq!(durability.to_string().parse(), Ok(durability));
+        }
+
+        assert!("sometimes".parse::<Durability>().is_err());
+    }
+
+    #[test]
+    fn never_only_tracks_pending() {
+        let mut tracker = SyncTracker::new(Durability::Never);
+
+        assert!(!tracker.record_op());
+        assert!(tracker.has_pending());
+    }
+}
@@ -0,0 +1,170 @@
+//! Writing records out in formats other tools can read.
+
+use std::{fmt, io::Write, str::FromStr};
+
+use crate::record::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line with every field.
+    JsonLines,
+    /// A header row, then one row per record with every field.
+    Csv,
+    /// Only the values of active records, one per line, the current one
+    /// marked with `> `. Backslashes, line breaks and a leading `>` are
+    /// escaped, so every value stays on its line. Meant for reading; the
+    /// other formats import back losslessly.
+    Text,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::JsonLines => write!(f, "jsonl"),
+            ExportFormat::Csv => write!(f, "csv"),
+            ExportFormat::Text => write!(f, "text"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ExportFormat::JsonLines),
+            "csv" => Ok(ExportFormat::Csv),
+            "text" => Ok(ExportFormat::Text),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Writes `records` in `format`. `current_id` is the record under the cursor,
+/// marked in the formats that have a field for it.
+pub fn write_records(
+    out: &mut impl Write,
+    records: &[Record],
+    current_id: Option<u64>,
+    format: ExportFormat,
+) -> std::io::Result<()> {
+    if format == ExportFormat::Csv {
+        writeln!(out, "id,active,created_at,current,data")?;
+    }
+
+    for record in records {
+        let id = record.meta.get_id();
+        let is_active = record.meta.is_active();
+        let is_current = is_active && current_id == Some(id);
+
+        match format {
+            ExportFormat::JsonLines => {
+                let created_at = record.meta.get_created_at().map_or("null".to_string(), |t| t.to_string());
+                writeln!(
+                    out,
+                    "{{\"id\":{id},\"active\":{is_active},\"created_at\":{created_at},\"current\":{is_current},\"data\":{}}}",
+                    json_string(&record.data)
+                )?;
+            }
+            ExportFormat::Csv => {
+                let created_at = record.meta.get_created_at().map_or(String::new(), |t| t.to_string());
+                writeln!(out, "{id},{is_active},{created_at},{is_current},{}", csv_field(&record.data))?;
+            }
+            ExportFormat::Text if is_active => {
+                let mark = if is_current { "> " } else { "" };
+                writeln!(out, "{mark}{}", text_line(&record.data))?;
+            }
+            ExportFormat::Text => {}
+        }
+    }
+
+    out.flush()
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+fn text_line(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    if value.starts_with('>') {
+        result.push('\\');
+    }
+
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Quotes a CSV field when it holds a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(records: &[Record], current_id: Option<u64>, format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        write_records(&mut out, records, current_id, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn formats_mark_cursor_and_escape_values() {
+        let records = [Record::new("plain".to_string(), 1), Record::new("say \"hi\", then\nleave".to_string(), 2)];
+
+        let jsonl = export(&records, Some(2), ExportFormat::JsonLines);
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert!(lines[0].starts_with("{\"id\":1,\"active\":true,\"created_at\":"));
+        assert!(lines[1].ends_with("\"current\":true,\"data\":\"say \\\"hi\\\", then\\nleave\"}"));
+
+        let csv = export(&records, Some(2), ExportFormat::Csv);
+        assert!(csv.starts_with("id,active,created_at,current,data\n1,true,"));
+        assert!(csv.ends_with(",true,\"say \"\"hi\"\", then\nleave\"\n"));
+
+        assert_eq!(export(&records[..1], None, ExportFormat::Text), "plain\n");
+    }
+
+    #[test]
+    fn text_marks_cursor_and_keeps_values_on_one_line() {
+        let records = [
+            Record::new("first".to_string(), 1),
+            Record::new("two\r\nlines \\o/".to_string(), 2),
+            Record::new("> quoted".to_string(), 3),
+        ];
+
+        assert_eq!(
+            export(&records, Some(2), ExportFormat::Text),
+            "first\n> two\\r\\nlines \\\\o/\n\\> quoted\n"
+        );
+    }
+}
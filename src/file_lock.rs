@@ -0,0 +1,99 @@
+use std::{
+    fs::{File, TryLockError},
+    io::{Error, ErrorKind},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// Many readers may hold the lock at once.
+    Shared,
+    /// A single writer holds the lock.
+    Exclusive,
+}
+
+/// Advisory lock on a file, released when dropped.
+///
+/// Holds its own handle to the file so the locked file stays usable while
+/// the lock is alive.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Tries to take the lock until `timeout` runs out. Contention past the
+    /// timeout is reported as `ErrorKind::WouldBlock`.
+    pub fn acquire(file: &File, kind: LockKind, timeout: Duration) -> std::io::Result<Self> {
+        let file = file.try_clone()?;
+        let started_at = Instant::now();
+
+        loop {
+            let result = match kind {
+                LockKind::Shared => file.try_lock_shared(),
+                LockKind::Exclusive => file.try_lock(),
+            };
+
+            match result {
+                Ok(()) => return Ok(Self { file }),
+                Err(TryLockError::WouldBlock) if started_at.elapsed() < timeout => {
+                    thread::sleep(Self::RETRY_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(Error::new(ErrorKind::WouldBlock, "file is locked by another process"));
+                }
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+
+    pub fn is_contention(e: &Error) -> bool {
+        e.kind() == ErrorKind::WouldBlock
+    }
+
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+    const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> (std::path::PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("re-queue-{}-{name}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn exclusive_lock_blocks_other_handles() {
+        let (path, file) = temp_file("exclusive");
+        let other = File::open(&path).unwrap();
+
+        let lock = FileLock::acquire(&file, LockKind::Exclusive, Duration::ZERO).unwrap();
+        let err = FileLock::acquire(&other, LockKind::Shared, Duration::ZERO).err().unwrap();
+        assert!(FileLock::is_contention(&err));
+
+        drop(lock);
+        assert!(FileLock::acquire(&other, LockKind::Shared, Duration::ZERO).is_ok());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn shared_locks_coexist() {
+        let (path, file) = temp_file("shared");
+        let other = File::open(&path).unwrap();
+
+        let _first = FileLock::acquire(&file, LockKind::Shared, Duration::ZERO).unwrap();
+        assert!(FileLock::acquire(&other, LockKind::Shared, Duration::ZERO).is_ok());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
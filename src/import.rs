@@ -0,0 +1,404 @@
+//! Reading records back from the formats `export` writes.
+
+use std::{collections::HashMap, fmt, io::BufRead, iter::Peekable, str::Chars};
+
+use crate::export::ExportFormat;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    /// `line` is where the offending value starts, counting from 1.
+    Parse { line: usize, message: String },
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "i/o error: {e}"),
+            ImportError::Parse { line, message } => write!(f, "{message} at line {line}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    pub added: u64,
+    /// Duplicates left out when deduplicating, and records exported as inactive.
+    pub skipped: u64,
+}
+
+/// What was read: the values to save, and how many inactive records were
+/// left out.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportedValues {
+    pub values: Vec<String>,
+    pub inactive: u64,
+}
+
+/// Reads values to save from `input`.
+///
+/// Text is one value per line, taken as written, blank lines ignored. JSON Lines takes either
+/// objects with a `data` field or plain strings. CSV needs a header row and
+/// takes the `data` column, or the only column there is. In both structured
+/// formats, records whose `active` field is false are left out.
+pub fn read_values(mut input: impl BufRead, format: ExportFormat) -> Result<ImportedValues, ImportError> {
+    let mut content = String::new();
+    input.read_to_string(&mut content)?;
+
+    match format {
+        ExportFormat::Text => Ok(ImportedValues {
+            values: content.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect(),
+            inactive: 0,
+        }),
+        ExportFormat::JsonLines => read_json_lines(&content),
+        ExportFormat::Csv => read_csv(&content),
+    }
+}
+
+fn read_json_lines(content: &str) -> Result<ImportedValues, ImportError> {
+    let mut result = ImportedValues::default();
+
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let error = |message: &str| ImportError::Parse { line: index + 1, message: message.to_string() };
+
+        match JsonParser::parse(line).map_err(|message| error(&message))? {
+            Json::String(value) => result.values.push(value),
+            Json::Object(mut fields) => {
+                if fields.get("active") == Some(&Json::Boolean(false)) {
+                    result.inactive += 1;
+                    continue;
+                }
+
+                match fields.remove("data") {
+                    Some(Json::String(value)) => result.values.push(value),
+                    _ => return Err(error("expected a string \"data\" field")),
+                }
+            }
+            _ => return Err(error("expected an object or a string")),
+        }
+    }
+
+    Ok(result)
+}
+
+fn read_csv(content: &str) -> Result<ImportedValues, ImportError> {
+    let mut rows = parse_csv(content)?.into_iter();
+    let mut result = ImportedValues::default();
+
+    let Some((_, header)) = rows.next() else {
+        return Ok(result);
+    };
+
+    let data_column = match header.iter().position(|name| name == "data") {
+        Some(column) => column,
+        None if header.len() == 1 => 0,
+        None => return Err(ImportError::Parse { line: 1, message: "no \"data\" column".to_string() }),
+    };
+    let active_column = header.iter().position(|name| name == "active");
+
+    for (line, mut row) in rows {
+        if row.len() != header.len() {
+            return Err(ImportError::Parse {
+                line,
+                message: format!("expected {} fields, found {}", header.len(), row.len()),
+            });
+        }
+
+        if active_column.is_some_and(|column| row[column] == "false") {
+            result.inactive += 1;
+            continue;
+        }
+
+        result.values.push(row.swap_remove(data_column));
+    }
+
+    Ok(result)
+}
+
+/// Rows along with the line each starts on. Quoted fields may span lines and
+/// use `""` for a quote.
+fn parse_csv(content: &str) -> Result<Vec<(usize, Vec<String>)>, ImportError> {
+    let mut rows = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let row_line = line;
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+
+        loop {
+            match chars.next() {
+                Some('"') if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                Some('"') if quoted => quoted = false,
+                Some('"') if field.is_empty() => quoted = true,
+                Some('\n') if quoted => {
+                    line += 1;
+                    field.push('\n');
+                }
+                Some(',') if !quoted => row.push(std::mem::take(&mut field)),
+                Some('\r') if !quoted && chars.peek() == Some(&'\n') => {}
+                Some('\n') | None if !quoted => {
+                    line += 1;
+                    row.push(std::mem::take(&mut field));
+                    break;
+                }
+                None => {
+                    return Err(ImportError::Parse { line: row_line, message: "unterminated quoted field".to_string() });
+                }
+                Some(c) => field.push(c),
+            }
+        }
+
+        // Blank lines, usually a trailing one, aren't rows
+        if row.len() > 1 || !row[0].is_empty() {
+            rows.push((row_line, row));
+        }
+    }
+
+    Ok(rows)
+}
+
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(HashMap<String, Json>),
+}
+
+/// Just enough JSON to read one value per line.
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn parse(input: &str) -> Result<Json, String> {
+        let mut parser = JsonParser { chars: input.chars().peekable() };
+        let value = parser.parse_value()?;
+
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected '{c}' after value")),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+
+        match self.chars.peek() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => self.parse_string().map(Json::String),
+            Some(c) if c.is_ascii_digit() || *c == '-' => self.parse_number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                    self.chars.next();
+                }
+
+                match word.as_str() {
+                    "true" => Ok(Json::Boolean(true)),
+                    "false" => Ok(Json::Boolean(false)),
+                    "null" => Ok(Json::Null),
+                    _ => Err(format!("unexpected '{word}'")),
+                }
+            }
+            _ => Err("expected a value".to_string()),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = HashMap::new();
+
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.insert(key, self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err("expected ',' or '}'".to_string()),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Json::Array(items));
+        }
+
+        loop {
+            items.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err("expected ',' or ']'".to_string()),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+
+        loop {
+            match self.chars.next() {
+                None => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(result),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('u') => result.push(self.parse_unicode_escape()?),
+                    _ => return Err("invalid escape sequence".to_string()),
+                },
+                Some(c) => result.push(c),
+            }
+        }
+    }
+
+    /// Handles characters outside the basic plane, which are written as a
+    /// surrogate pair of escapes.
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err("unpaired surrogate".to_string());
+            }
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err("unpaired surrogate".to_string());
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| "invalid unicode escape".to_string())
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| "invalid unicode escape".to_string())?;
+            code = code * 16 + digit;
+        }
+
+        Ok(code)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let mut number = String::new();
+        while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_digit() || "+-.eE".contains(**c)) {
+            number.push(c);
+            self.chars.next();
+        }
+
+        number.parse().map(Json::Number).map_err(|_| format!("invalid number '{number}'"))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(format!("expected '{expected}'")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    #[test]
+    fn reads_back_what_export_writes() {
+        let values = ["say \"hi\", then\nleave", "removed", "snow \u{2603} and \u{1F600}"];
+        let mut storage = Storage::in_memory();
+        for value in values {
+            storage.save(value.to_string()).unwrap();
+        }
+        storage.remove(2).unwrap();
+
+        for format in [ExportFormat::JsonLines, ExportFormat::Csv] {
+            let mut out = Vec::new();
+            storage.export(&mut out, format).unwrap();
+
+            let imported = read_values(out.as_slice(), format).unwrap();
+            assert_eq!(imported.values, [values[0], values[2]]);
+            assert_eq!(imported.inactive, 1);
+        }
+    }
+
+    #[test]
+    fn reads_loose_input() {
+        let jsonl = "\"plain\"\n\n{\"data\": \"\\ud83d\\ude00\", \"tags\": [1, null]}\n";
+        assert_eq!(read_values(jsonl.as_bytes(), ExportFormat::JsonLines).unwrap().values, ["plain", "\u{1F600}"]);
+
+        let csv = "value\r\nfirst\r\n\"second, quoted\"\r\n";
+        assert_eq!(read_values(csv.as_bytes(), ExportFormat::Csv).unwrap().values, ["first", "second, quoted"]);
+
+        let text = "first\n\n  second  \n";
+        assert_eq!(read_values(text.as_bytes(), ExportFormat::Text).unwrap().values, ["first", "  second  "]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = read_values("\"ok\"\n{\"data\": 1}\n".as_bytes(), ExportFormat::JsonLines).unwrap_err();
+        assert_eq!(error.to_string(), "expected a string \"data\" field at line 2");
+
+        let error = read_values("id,data\n1,a\n2\n".as_bytes(), ExportFormat::Csv).unwrap_err();
+        assert_eq!(error.to_string(), "expected 2 fields, found 1 at line 3");
+    }
+}
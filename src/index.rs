@@ -0,0 +1,227 @@
+//! Full-text index of a storage's active records, kept in its `index`
+//! sidecar.
+//!
+//! The sidecar starts with a header naming the build, followed by a log of
+//! records added to and dropped from the index, by pointer:
+//!
+//! ```text
+//! re-queue index 1 1760000000000-4242-0
+//! +0 buy milk
+//! +54 call bob
+//! -0
+//! ```
+//!
+//! Changes are only appended, so a reader that already parsed the index
+//! catches up by reading what was added since. Building the index again, or
+//! compacting the log once most of it is dropped entries, writes a new
+//! header, which tells readers to start over.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::record::Record;
+
+pub const SIDECAR: &str = "index";
+
+const HEADER_PREFIX: &str = "re-queue index 1 ";
+
+/// Lowercased runs of letters and digits.
+pub fn words(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Header for a new build, different from every earlier one.
+pub fn header() -> String {
+    static BUILDS: AtomicU64 = AtomicU64::new(0);
+
+    let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+    format!("{HEADER_PREFIX}{}-{}-{build}\n", Record::now(), process::id())
+}
+
+pub fn add_line(pointer: u64, text: &str) -> String {
+    entry_line(pointer, words(text))
+}
+
+fn entry_line(pointer: u64, words: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    let mut line = format!("+{pointer}");
+    for word in words {
+        line.push(' ');
+        line.push_str(word.as_ref());
+    }
+    line.push('\n');
+
+    line
+}
+
+pub fn remove_line(pointer: u64) -> String {
+    format!("-{pointer}\n")
+}
+
+#[derive(Debug, Default)]
+pub struct Index {
+    header: String,
+    /// Bytes of the sidecar read so far.
+    consumed: u64,
+    postings: BTreeMap<String, BTreeSet<u64>>,
+    words_by_pointer: HashMap<u64, Vec<String>>,
+    /// Lines read that no longer describe an entry: removals, and the adds
+    /// they or later adds for the same pointer replaced.
+    dead_lines: u64,
+}
+
+impl Index {
+    /// Reads a whole sidecar. `None` if it doesn't start with a header.
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        let header_len = content.iter().position(|&b| b == b'\n')? + 1;
+        let header = std::str::from_utf8(&content[..header_len]).ok()?;
+        if !header.starts_with(HEADER_PREFIX) {
+            return None;
+        }
+
+        let mut index = Self { header: header.to_string(), consumed: header_len as u64, ..Self::default() };
+        index.catch_up(&content[header_len..]);
+
+        Some(index)
+    }
+
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Applies the complete lines of what was appended to the sidecar since
+    /// it was last read. A line being written right now is left for later.
+    pub fn catch_up(&mut self, appended: &[u8]) {
+        let Some(end) = appended.iter().rposition(|&b| b == b'\n').map(|i| i + 1) else {
+            return;
+        };
+
+        // Lines torn by a crash don't parse and are skipped
+        for line in String::from_utf8_lossy(&appended[..end]).lines() {
+            self.apply(line);
+        }
+        self.consumed += end as u64;
+    }
+
+    fn apply(&mut self, line: &str) {
+        if let Some(rest) = line.strip_prefix('+') {
+            let mut fields = rest.split(' ');
+            if let Some(Ok(pointer)) = fields.next().map(str::parse) {
+                if self.remove(pointer) {
+                    self.dead_lines += 1;
+                }
+
+                let words: Vec<String> = fields.filter(|word| !word.is_empty()).map(str::to_string).collect();
+                for word in &words {
+                    self.postings.entry(word.clone()).or_default().insert(pointer);
+                }
+                self.words_by_pointer.insert(pointer, words);
+            }
+        } else if let Some(Ok(pointer)) = line.strip_prefix('-').map(str::parse) {
+            self.dead_lines += 1 + self.remove(pointer) as u64;
+        }
+    }
+
+    /// Whether the entry was there.
+    fn remove(&mut self, pointer: u64) -> bool {
+        let Some(words) = self.words_by_pointer.remove(&pointer) else {
+            return false;
+        };
+
+        for word in words {
+            if let Some(pointers) = self.postings.get_mut(&word) {
+                pointers.remove(&pointer);
+                if pointers.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Whether most of the log is dead lines, so `to_log` would shrink it.
+    pub fn needs_compaction(&self) -> bool {
+        self.dead_lines > self.words_by_pointer.len() as u64
+    }
+
+    /// A log under a new header holding just the current entries.
+    pub fn to_log(&self) -> String {
+        let mut pointers: Vec<&u64> = self.words_by_pointer.keys().collect();
+        pointers.sort();
+
+        let mut log = header();
+        for pointer in pointers {
+            log.push_str(&entry_line(*pointer, &self.words_by_pointer[pointer]));
+        }
+
+        log
+    }
+
+    /// Pointers of the records holding every word of `query`, in order. With
+    /// `prefix`, a query word also matches the longer words it starts.
+    pub fn search(&self, query: &str, prefix: bool) -> Vec<u64> {
+        let mut result: Option<BTreeSet<u64>> = None;
+
+        for word in words(query) {
+            let matches: BTreeSet<u64> = if prefix {
+                self.postings
+                    .range(word.clone()..)
+                    .take_while(|(indexed, _)| indexed.starts_with(&word))
+                    .flat_map(|(_, pointers)| pointers.iter().copied())
+                    .collect()
+            } else {
+                self.postings.get(&word).cloned().unwrap_or_default()
+            };
+
+            result = Some(match result {
+                Some(result) => result.intersection(&matches).copied().collect(),
+                None => matches,
+            });
+        }
+
+        result.unwrap_or_default().into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_replays_into_postings() {
+        let log = [header(), add_line(0, "Buy milk, then call Bob"), add_line(60, "bob's milkshake"), remove_line(0)]
+            .concat();
+        let mut index = Index::parse(log.as_bytes()).unwrap();
+
+        assert_eq!(index.search("milk", false), Vec::<u64>::new());
+        assert_eq!(index.search("MILK bob", true), [60]);
+
+        // Half a line is held back until the rest arrives
+        index.catch_up(b"+90 bobby\n+120 bo");
+        assert_eq!(index.search("bob", true), [60, 90]);
+        assert_eq!(index.consumed(), log.len() as u64 + 10);
+    }
+
+    #[test]
+    fn compacted_log_keeps_only_live_entries() {
+        let log = [header(), add_line(0, "milk"), add_line(10, "bob"), remove_line(0), add_line(10, "bob milk")].concat();
+        let index = Index::parse(log.as_bytes()).unwrap();
+        assert!(index.needs_compaction());
+
+        let compacted = Index::parse(index.to_log().as_bytes()).unwrap();
+        assert!(!compacted.needs_compaction());
+        assert_ne!(compacted.header(), index.header());
+        assert_eq!(compacted.search("milk", false), [10]);
+        assert_eq!(compacted.consumed(), compacted.header().len() as u64 + "+10 bob milk\n".len() as u64);
+    }
+}
@@ -0,0 +1,397 @@
+//! Consistency checks over a storage's meta and record stream.
+
+use std::fmt;
+
+use crate::{backend::StorageBackend, checksum, meta::Meta, record::Record, record_header::RecordHeader};
+
+/// Something wrong found while checking a storage. Pointers are byte offsets
+/// into the record stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// The record starting here runs past the end of the data.
+    TruncatedRecord { pointer: u64 },
+    /// The header starting here can't be a record header.
+    DamagedHeader { pointer: u64 },
+    /// The payload of the record starting here isn't UTF-8.
+    InvalidPayload { pointer: u64, id: u64 },
+    /// The payload of the record starting here doesn't match its checksum.
+    ChecksumMismatch { pointer: u64, id: u64 },
+    /// Ids are expected to grow; records merged with their own ids can break that.
+    IdOutOfOrder { pointer: u64, id: u64, previous_id: u64 },
+    /// The meta points past the last readable record.
+    WritePointerPastData { write_pointer: u64, data_end: u64 },
+    /// Readable records follow the write pointer, usually left by a crash
+    /// between writing a record and updating the meta.
+    UnreferencedRecords { write_pointer: u64, data_end: u64 },
+    /// The write pointer falls inside a record.
+    WritePointerMisaligned { write_pointer: u64 },
+    /// The cursor doesn't point at the start of a record.
+    ReadPointerMisaligned { read_pointer: u64 },
+    /// The id counter is behind ids already handed out.
+    TotalRecordsAddedTooLow { total_records_added: u64, max_id: u64 },
+    /// The meta's count of active records is off.
+    ActiveRecordsMiscounted { active_records: u64, counted: u64 },
+}
+
+impl VerifyIssue {
+    /// Whether `Storage::repair` does something about it.
+    pub fn is_repairable(&self) -> bool {
+        !matches!(self, VerifyIssue::IdOutOfOrder { .. })
+    }
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::TruncatedRecord { pointer } => write!(f, "record at {pointer} is cut short"),
+            VerifyIssue::DamagedHeader { pointer } => write!(f, "record header at {pointer} is damaged"),
+            VerifyIssue::InvalidPayload { pointer, id } => write!(f, "record {id} at {pointer} is not valid UTF-8"),
+            VerifyIssue::ChecksumMismatch { pointer, id } => write!(f, "record {id} at {pointer} fails its checksum"),
+            VerifyIssue::IdOutOfOrder { pointer, id, previous_id } => {
+                write!(f, "record {id} at {pointer} comes after record {previous_id}")
+            }
+            VerifyIssue::WritePointerPastData { write_pointer, data_end } => {
+                write!(f, "write pointer {write_pointer} is past the last readable record, which ends at {data_end}")
+            }
+            VerifyIssue::UnreferencedRecords { write_pointer, data_end } => {
+                write!(f, "records between {write_pointer} and {data_end} are past the write pointer")
+            }
+            VerifyIssue::WritePointerMisaligned { write_pointer } => {
+                write!(f, "write pointer {write_pointer} is inside a record")
+            }
+            VerifyIssue::ReadPointerMisaligned { read_pointer } => {
+                write!(f, "read pointer {read_pointer} is not at the start of a record")
+            }
+            VerifyIssue::TotalRecordsAddedTooLow { total_records_added, max_id } => {
+                write!(f, "{total_records_added} records were added, but ids go up to {max_id}")
+            }
+            VerifyIssue::ActiveRecordsMiscounted { active_records, counted } => {
+                write!(f, "meta counts {active_records} active records, but there are {counted}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Readable records, inactive ones included.
+    pub records: u64,
+    pub active_records: u64,
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Result of walking the record stream, with what's needed to repair it.
+pub(crate) struct Scan {
+    pub report: VerifyReport,
+    /// End of the last readable record; anything after it is lost.
+    pub data_end: u64,
+    boundaries: Vec<u64>,
+    max_id: u64,
+    /// Active records before `data_end`.
+    active_records: u64,
+}
+
+impl Scan {
+    /// Walks every record, stopping at the first one that can't be read,
+    /// and checks the meta against what was found.
+    pub fn run(backend: &mut dyn StorageBackend, meta: &Meta) -> std::io::Result<Self> {
+        let mut data = DataWindow::new(backend)?;
+        let data_len = data.len;
+
+        let mut report = VerifyReport::default();
+        let mut boundaries = Vec::new();
+        let mut previous_id = None;
+        let mut max_id = 0;
+        let mut active_before_write_pointer = 0;
+        let mut pointer = 0;
+
+        while pointer < data_len {
+            let (header, end) = match check_record(&mut data, pointer)? {
+                Ok(record) => record,
+                Err(issue) => {
+                    report.issues.push(issue);
+                    break;
+                }
+            };
+
+            let id = header.get_id();
+            if let Some(previous_id) = previous_id
+                && id <= previous_id
+            {
+                report.issues.push(VerifyIssue::IdOutOfOrder { pointer, id, previous_id });
+            }
+
+            previous_id = Some(id);
+            max_id = max_id.max(id);
+            report.records += 1;
+            report.active_records += header.is_active() as u64;
+            if pointer < meta.write_pointer {
+                active_before_write_pointer += header.is_active() as u64;
+            }
+            boundaries.push(pointer);
+            pointer = end;
+        }
+
+        let data_end = pointer;
+        boundaries.push(data_end);
+
+        if meta.write_pointer > data_end {
+            report.issues.push(VerifyIssue::WritePointerPastData { write_pointer: meta.write_pointer, data_end });
+        } else if !boundaries.contains(&meta.write_pointer) {
+            report.issues.push(VerifyIssue::WritePointerMisaligned { write_pointer: meta.write_pointer });
+        } else if meta.write_pointer < data_end {
+            report.issues.push(VerifyIssue::UnreferencedRecords { write_pointer: meta.write_pointer, data_end });
+        }
+
+        if meta.read_pointer > meta.write_pointer || !boundaries.contains(&meta.read_pointer) {
+            report.issues.push(VerifyIssue::ReadPointerMisaligned { read_pointer: meta.read_pointer });
+        }
+
+        if meta.total_records_added < max_id {
+            report.issues.push(VerifyIssue::TotalRecordsAddedTooLow {
+                total_records_added: meta.total_records_added,
+                max_id,
+            });
+        }
+
+        if let Some(active_records) = meta.active_records
+            && active_records != active_before_write_pointer
+        {
+            report.issues.push(VerifyIssue::ActiveRecordsMiscounted {
+                active_records,
+                counted: active_before_write_pointer,
+            });
+        }
+
+        let active_records = report.active_records;
+        Ok(Self { report, data_end, boundaries, max_id, active_records })
+    }
+
+    /// Meta that agrees with the readable records: the write pointer moves to
+    /// the end of them, the cursor to the nearest record at or after it.
+    pub fn repaired_meta(&self, meta: Meta) -> Meta {
+        let read_pointer = self
+            .boundaries
+            .iter()
+            .copied()
+            .find(|&boundary| boundary >= meta.read_pointer)
+            .unwrap_or(self.data_end);
+
+        Meta {
+            read_pointer,
+            write_pointer: self.data_end,
+            total_records_added: meta.total_records_added.max(self.max_id),
+            active_records: Some(self.active_records),
+            ..meta
+        }
+    }
+}
+
+/// Reads the record stream a window at a time, so checking a storage doesn't
+/// hold all of it in memory. Only a record larger than the window is read in
+/// one piece.
+struct DataWindow<'a> {
+    backend: &'a mut dyn StorageBackend,
+    len: u64,
+    start: u64,
+    buffer: Vec<u8>,
+}
+
+impl<'a> DataWindow<'a> {
+    const SIZE: u64 = 64 * 1024;
+
+    fn new(backend: &'a mut dyn StorageBackend) -> std::io::Result<Self> {
+        let len = backend.data_len()?;
+        Ok(Self { backend, len, start: 0, buffer: Vec::new() })
+    }
+
+    /// The `len` bytes at `pointer`, `None` if they run past the data.
+    fn get(&mut self, pointer: u64, len: u64) -> std::io::Result<Option<&[u8]>> {
+        let Some(end) = pointer.checked_add(len).filter(|&end| end <= self.len) else {
+            return Ok(None);
+        };
+
+        if pointer < self.start || end > self.start + self.buffer.len() as u64 {
+            let read_len = len.max(Self::SIZE).min(self.len - pointer);
+            self.buffer = self.backend.read_at(pointer, read_len as usize)?;
+            self.start = pointer;
+        }
+
+        let offset = (pointer - self.start) as usize;
+        Ok(Some(&self.buffer[offset..offset + len as usize]))
+    }
+}
+
+/// Checks the record starting at `pointer` and returns its header and where
+/// it ends.
+fn check_record(data: &mut DataWindow, pointer: u64) -> std::io::Result<Result<(RecordHeader, u64), VerifyIssue>> {
+    let header_size = RecordHeader::size() as u64;
+
+    let Some(header_bytes) = data.get(pointer, header_size)? else {
+        return Ok(Err(VerifyIssue::TruncatedRecord { pointer }));
+    };
+    let header = RecordHeader::from_bytes(header_bytes);
+    if header_bytes[RecordHeader::ACTIVE_FLAG_OFFSET as usize] > 1 || header.is_garbled() {
+        return Ok(Err(VerifyIssue::DamagedHeader { pointer }));
+    }
+
+    let Some(payload) = data.get(pointer + header_size, header.get_content_size())? else {
+        return Ok(Err(VerifyIssue::TruncatedRecord { pointer }));
+    };
+
+    let id = header.get_id();
+    if header.is_framed() && checksum::crc32(payload) != header.get_checksum() {
+        return Ok(Err(VerifyIssue::ChecksumMismatch { pointer, id }));
+    }
+    if std::str::from_utf8(payload).is_err() {
+        return Ok(Err(VerifyIssue::InvalidPayload { pointer, id }));
+    }
+
+    Ok(Ok((header, pointer + header_size + payload.len() as u64)))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Active records found and copied.
+    pub recovered: u64,
+    /// Byte ranges that had to be skipped, as `(start, end)`.
+    pub damaged: Vec<(u64, u64)>,
+}
+
+/// Hands every active record that can still be read to `keep`, skipping
+/// over damage.
+///
+/// After a record fails to read, the scan moves forward to the next framed
+/// header whose payload matches its checksum, so only the damaged records
+/// are lost. Unframed records can't be found that way and are only kept
+/// while they follow each other undamaged.
+pub(crate) fn salvage<E: From<std::io::Error>>(
+    backend: &mut dyn StorageBackend,
+    mut keep: impl FnMut(Record) -> Result<(), E>,
+) -> Result<SalvageReport, E> {
+    let mut data = DataWindow::new(backend)?;
+    let mut report = SalvageReport::default();
+    let mut pointer = 0;
+
+    while pointer < data.len {
+        match check_record(&mut data, pointer)? {
+            Ok((header, end)) => {
+                if header.is_active() {
+                    let payload_start = pointer + RecordHeader::size() as u64;
+                    let payload = data.get(payload_start, end - payload_start)?.unwrap_or_default();
+                    keep(Record::from_bytes(header, payload)?)?;
+                    report.recovered += 1;
+                }
+                pointer = end;
+            }
+            Err(_) => {
+                let next = next_framed_record(&mut data, pointer + 1)?.unwrap_or(data.len);
+                report.damaged.push((pointer, next));
+                pointer = next;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn next_framed_record(data: &mut DataWindow, from: u64) -> std::io::Result<Option<u64>> {
+    let magic_len = RecordHeader::MAGIC.len() as u64;
+    let mut search_from = from + RecordHeader::MAGIC_OFFSET as u64;
+
+    loop {
+        let len = data.len.saturating_sub(search_from).min(DataWindow::SIZE);
+        if len < magic_len {
+            return Ok(None);
+        }
+
+        let found = data
+            .get(search_from, len)?
+            .and_then(|chunk| chunk.windows(RecordHeader::MAGIC.len()).position(|window| window == RecordHeader::MAGIC));
+
+        match found {
+            Some(found) => {
+                let pointer = search_from + found as u64 - RecordHeader::MAGIC_OFFSET as u64;
+                if check_record(data, pointer)?.is_ok() {
+                    return Ok(Some(pointer));
+                }
+                search_from += found as u64 + 1;
+            }
+            // The magic may straddle the end of this chunk
+            None => search_from += len - magic_len + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_backend::MemoryBackend;
+
+    fn records_data(values: &[&str]) -> Vec<u8> {
+        values
+            .iter()
+            .enumerate()
+            .flat_map(|(index, value)| Record::new(value.to_string(), index as u64 + 1).to_bytes())
+            .collect()
+    }
+
+    fn backend(data: Vec<u8>) -> MemoryBackend {
+        MemoryBackend::from_parts(Meta::default(), data)
+    }
+
+    #[test]
+    fn salvage_loses_only_the_damaged_record() {
+        let mut data = records_data(&["first", "second", "third"]);
+        // Garble the content size of the second record, so following sizes
+        // can't get past it
+        data[54 + 1] ^= 0x40;
+
+        let mut records = Vec::new();
+        let report = salvage::<std::io::Error>(&mut backend(data), |record| {
+            records.push(record);
+            Ok(())
+        })
+        .unwrap();
+
+        let ids: Vec<u64> = records.iter().map(|r| r.meta.get_id()).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(report.damaged, [(54, 109)]);
+    }
+
+    #[test]
+    fn salvage_reads_past_the_first_window() {
+        let values: Vec<String> = (1000..4000).map(|n| format!("value {n}")).collect();
+        let mut data = records_data(&values.iter().map(String::as_str).collect::<Vec<_>>());
+        let record_size = data.len() / values.len();
+        let damaged = 2000 * record_size;
+        data[damaged + 1] ^= 0x40;
+
+        let mut ids = Vec::new();
+        let report = salvage::<std::io::Error>(&mut backend(data), |record| {
+            ids.push(record.meta.get_id());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(report.recovered, 2999);
+        assert!(!ids.contains(&2001));
+        assert_eq!(report.damaged, [(damaged as u64, (damaged + record_size) as u64)]);
+    }
+
+    #[test]
+    fn checksum_catches_damaged_payload() {
+        let mut data = records_data(&["first"]);
+        data[RecordHeader::size()] = b'F';
+
+        let mut backend = backend(data);
+        let mut window = DataWindow::new(&mut backend).unwrap();
+        assert_eq!(check_record(&mut window, 0).unwrap().err(), Some(VerifyIssue::ChecksumMismatch { pointer: 0, id: 1 }));
+    }
+}
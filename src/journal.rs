@@ -0,0 +1,175 @@
+//! Journal of recent operations on a storage, kept in its `journal` sidecar
+//! so they can be undone and redone.
+//!
+//! The sidecar is a log of one line per operation, plus `undo` and `redo`
+//! lines; replaying it gives the current state. It's only appended to, and
+//! rewritten in compact form now and then.
+
+use std::fmt;
+
+pub const SIDECAR: &str = "journal";
+
+/// How many operations can be undone.
+pub const LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Save,
+    Remove,
+    MoveNext,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Save => write!(f, "save"),
+            Operation::Remove => write!(f, "remove"),
+            Operation::MoveNext => write!(f, "next"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub operation: Operation,
+    pub read_pointer_before: u64,
+    pub read_pointer_after: u64,
+    /// Pointer and id of the record saved or removed.
+    pub record: Option<(u64, u64)>,
+}
+
+impl JournalEntry {
+    pub fn to_line(&self) -> String {
+        let mut line = format!("{} {} {}", self.operation, self.read_pointer_before, self.read_pointer_after);
+        if let Some((pointer, id)) = self.record {
+            line.push_str(&format!(" {pointer} {id}"));
+        }
+        line.push('\n');
+
+        line
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split(' ');
+        let operation = match fields.next()? {
+            "save" => Operation::Save,
+            "remove" => Operation::Remove,
+            "next" => Operation::MoveNext,
+            _ => return None,
+        };
+
+        let numbers: Vec<u64> = fields.map(|field| field.parse().ok()).collect::<Option<_>>()?;
+        let record = match (operation, numbers.as_slice()) {
+            (Operation::MoveNext, [_, _]) => None,
+            (Operation::Save | Operation::Remove, [_, _, pointer, id]) => Some((*pointer, *id)),
+            _ => return None,
+        };
+
+        Some(Self { operation, read_pointer_before: numbers[0], read_pointer_after: numbers[1], record })
+    }
+}
+
+pub const UNDO_LINE: &str = "undo\n";
+pub const REDO_LINE: &str = "redo\n";
+
+/// Operations up to `position` are done, the ones after it undone.
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    position: usize,
+}
+
+impl Journal {
+    /// Replays a journal log. Lines that can't be read, like one cut short
+    /// by a crash, are skipped.
+    pub fn parse(content: &str) -> Self {
+        let mut journal = Self::default();
+
+        for line in content.lines() {
+            match line {
+                "undo" => {
+                    journal.undo();
+                }
+                "redo" => {
+                    journal.redo();
+                }
+                line => {
+                    if let Some(entry) = JournalEntry::from_line(line) {
+                        journal.record(entry);
+                    }
+                }
+            }
+        }
+
+        journal
+    }
+
+    /// The shortest log that replays to the same state.
+    pub fn to_log(&self) -> String {
+        let mut log: String = self.entries.iter().map(JournalEntry::to_line).collect();
+        for _ in self.position..self.entries.len() {
+            log.push_str(UNDO_LINE);
+        }
+
+        log
+    }
+
+    /// Adds an operation, dropping whatever was undone before it.
+    pub fn record(&mut self, entry: JournalEntry) {
+        self.entries.truncate(self.position);
+        self.entries.push(entry);
+
+        if self.entries.len() > LIMIT {
+            self.entries.remove(0);
+        }
+        self.position = self.entries.len();
+    }
+
+    pub fn undo(&mut self) -> Option<JournalEntry> {
+        self.position = self.position.checked_sub(1)?;
+        Some(self.entries[self.position])
+    }
+
+    pub fn redo(&mut self) -> Option<JournalEntry> {
+        let entry = *self.entries.get(self.position)?;
+        self.position += 1;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(from: u64, to: u64) -> JournalEntry {
+        JournalEntry { operation: Operation::MoveNext, read_pointer_before: from, read_pointer_after: to, record: None }
+    }
+
+    #[test]
+    fn replay_matches_compacted_log() {
+        let save = JournalEntry { operation: Operation::Save, read_pointer_before: 0, read_pointer_after: 0, record: Some((0, 1)) };
+        let log = [save.to_line(), next(0, 54).to_line(), UNDO_LINE.to_string(), "next 54".to_string()].concat();
+
+        let mut journal = Journal::parse(&log);
+        assert_eq!(Journal::parse(&journal.to_log()).to_log(), journal.to_log());
+
+        assert_eq!(journal.redo(), Some(next(0, 54)));
+        assert_eq!(journal.redo(), None);
+        assert_eq!(journal.undo(), Some(next(0, 54)));
+        assert_eq!(journal.undo(), Some(save));
+        assert_eq!(journal.undo(), None);
+    }
+
+    #[test]
+    fn new_operation_drops_undone_ones() {
+        let mut journal = Journal::default();
+        journal.record(next(0, 1));
+        journal.record(next(1, 2));
+        journal.undo();
+        journal.record(next(1, 3));
+
+        assert_eq!(journal.redo(), None);
+        assert_eq!(journal.undo(), Some(next(1, 3)));
+        assert_eq!(journal.undo(), Some(next(0, 1)));
+    }
+}
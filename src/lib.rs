@@ -7,3 +7,24 @@ pub mod meta_store;
 pub mod storage_manager;
 pub mod config;
 pub mod config_store;
+pub mod config_format;
+pub mod durability;
+pub mod file_lock;
+pub mod shared;
+pub mod notifier;
+pub mod backend;
+pub mod memory_backend;
+pub mod paths;
+pub mod integrity;
+pub mod checksum;
+pub mod export;
+pub mod import;
+pub mod backup;
+pub mod snapshot;
+pub mod journal;
+pub mod audit;
+pub mod regex;
+pub mod search;
+pub mod index;
+#[cfg(feature = "async")]
+pub mod pick_future;
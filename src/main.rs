@@ -1,8 +1,58 @@
+use std::{env, path::PathBuf, process};
+
+use re_queue::paths::StoragePaths;
+
 use crate::app::App;
 
 pub mod app;
 
+const USAGE: &str = "Usage: re-queue [--home <dir>]";
+
 fn main() {
-    let mut app = App::new();
+    let mut home: Option<PathBuf> = None;
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--home" => match args.next() {
+                Some(dir) => home = Some(PathBuf::from(dir)),
+                None => {
+                    eprintln!("--home requires a directory\n{USAGE}");
+                    process::exit(2);
+                }
+            },
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                println!("Without --home, {} or the XDG data and config directories are used.", StoragePaths::HOME_ENV);
+                return;
+            }
+            _ => {
+                eprintln!("Unknown argument: {arg}\n{USAGE}");
+                process::exit(2);
+            }
+        }
+    }
+
+    let explicit = home.is_some();
+    let paths = StoragePaths::resolve(home);
+    if !explicit
+        && paths == StoragePaths::working_directory()
+        && let Some(xdg) = StoragePaths::xdg()
+    {
+        println!(
+            "Using the config and storages in the working directory, where older versions kept them. \
+             Move them to {} and {}, or pass --home.",
+            xdg.config_path.display(),
+            xdg.storage_dir.display()
+        );
+    }
+
+    let mut app = match App::new(paths) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }
+    };
     app.run();
 }
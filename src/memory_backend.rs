@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    backend::{BackendLock, StorageBackend},
+    file_lock::LockKind,
+    meta::Meta,
+    notifier::Notifier,
+    record::Record,
+    record_header::RecordHeader,
+};
+
+struct MemoryData {
+    meta: Meta,
+    data: Vec<u8>,
+    sidecars: HashMap<String, Vec<u8>>,
+}
+
+/// Holders of a `MemoryBackend`'s lock: any number of readers, or one writer.
+#[derive(Default)]
+struct LockState {
+    readers: usize,
+    writer: bool,
+}
+
+#[derive(Default)]
+struct SharedLock {
+    state: Mutex<LockState>,
+    released: Condvar,
+}
+
+impl SharedLock {
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A `MemoryBackend`'s lock, released when dropped.
+pub struct MemoryLock {
+    shared: Arc<SharedLock>,
+    kind: LockKind,
+}
+
+impl MemoryLock {
+    fn acquire(shared: &Arc<SharedLock>, kind: LockKind, timeout: Duration) -> std::io::Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let mut state = shared.state();
+
+        loop {
+            let is_free = match kind {
+                LockKind::Shared => !state.writer,
+                LockKind::Exclusive => !state.writer && state.readers == 0,
+            };
+            if is_free {
+                break;
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(Error::new(ErrorKind::WouldBlock, "storage is locked by another handle"));
+            }
+            state = shared.released.wait_timeout(state, left).unwrap_or_else(|e| e.into_inner()).0;
+        }
+
+        match kind {
+            LockKind::Shared => state.readers += 1,
+            LockKind::Exclusive => state.writer = true,
+        }
+
+        Ok(Self { shared: shared.clone(), kind })
+    }
+}
+
+impl Drop for MemoryLock {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        match self.kind {
+            LockKind::Shared => state.readers -= 1,
+            LockKind::Exclusive => state.writer = false,
+        }
+        self.shared.released.notify_all();
+    }
+}
+
+/// Storage backend that keeps meta and records in memory.
+///
+/// Clones share the same data and lock, the same way two `FileBackend`s
+/// opened on the same files do.
+#[derive(Clone)]
+pub struct MemoryBackend {
+    inner: Arc<Mutex<MemoryData>>,
+    lock: Arc<SharedLock>,
+    notifier: Arc<Notifier>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::from_parts(Meta::default(), Vec::new())
+    }
+
+    /// Independent copy of the current contents, as copying the files would give.
+    pub fn duplicate(&self) -> Self {
+        let (meta, data) = self.to_parts();
+        let duplicate = Self::from_parts(meta, data);
+        duplicate.data().sidecars = self.data().sidecars.clone();
+
+        duplicate
+    }
+
+    /// Backend holding the given meta and record stream, as opening copied
+    /// files would give.
+    pub fn from_parts(meta: Meta, data: Vec<u8>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(MemoryData { meta, data, sidecars: HashMap::new() })),
+            lock: Arc::default(),
+            notifier: Arc::new(Notifier::new()),
+        }
+    }
+
+    pub fn to_parts(&self) -> (Meta, Vec<u8>) {
+        let memory = self.data();
+        (memory.meta, memory.data.clone())
+    }
+
+    fn data(&self) -> MutexGuard<'_, MemoryData> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get_meta(&mut self) -> std::io::Result<Meta> {
+        Ok(self.data().meta)
+    }
+
+    fn update_meta(&mut self, meta: Meta) -> std::io::Result<()> {
+        self.data().meta = meta;
+        Ok(())
+    }
+
+    fn push(&mut self, record: &Record) -> std::io::Result<u64> {
+        let bytes = record.to_bytes();
+        self.data().data.extend_from_slice(&bytes);
+
+        Ok(bytes.len() as u64)
+    }
+
+    fn pick(&mut self, pointer: u64) -> std::io::Result<Record> {
+        let memory = self.data();
+        let unexpected_eof = || Error::from(ErrorKind::UnexpectedEof);
+
+        let header_start = usize::try_from(pointer).map_err(|_| unexpected_eof())?;
+        let header_end = header_start.checked_add(RecordHeader::size()).ok_or_else(unexpected_eof)?;
+        let header = RecordHeader::from_bytes(memory.data.get(header_start..header_end).ok_or_else(unexpected_eof)?);
+
+        let data_end = header.record_end(pointer, memory.data.len() as u64)? as usize;
+        let data = &memory.data[header_end..data_end];
+
+        Record::from_bytes(header, data)
+    }
+
+    fn get_all(&mut self) -> std::io::Result<Vec<Record>> {
+        let mut result = Vec::<Record>::new();
+        let mut pointer = 0u64;
+
+        while let Ok(record) = self.pick(pointer) {
+            pointer += record.size();
+            result.push(record);
+        }
+
+        Ok(result)
+    }
+
+    fn data_len(&mut self) -> std::io::Result<u64> {
+        Ok(self.data().data.len() as u64)
+    }
+
+    fn read_at(&mut self, pointer: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let start = pointer as usize;
+        self.data()
+            .data
+            .get(start..start + len)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))
+    }
+
+    fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+        self.data().data.truncate(len as usize);
+        Ok(())
+    }
+
+    fn set_active(&mut self, pointer: u64, is_active: bool) -> std::io::Result<()> {
+        let mut memory = self.data();
+        let flag = memory
+            .data
+            .get_mut((pointer + RecordHeader::ACTIVE_FLAG_OFFSET) as usize)
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))?;
+
+        *flag = is_active as u8;
+
+        Ok(())
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn lock(&mut self, kind: LockKind, timeout: Duration) -> std::io::Result<BackendLock> {
+        Ok(BackendLock::Memory(MemoryLock::acquire(&self.lock, kind, timeout)?))
+    }
+
+    fn notifier(&self) -> Arc<Notifier> {
+        self.notifier.clone()
+    }
+
+    fn read_sidecar(&mut self, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.data().sidecars.get(name).cloned())
+    }
+
+    fn sidecar_len(&mut self, name: &str) -> std::io::Result<Option<u64>> {
+        Ok(self.data().sidecars.get(name).map(|content| content.len() as u64))
+    }
+
+    fn read_sidecar_range(&mut self, name: &str, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let data = self.data();
+        let content = data
+            .sidecars
+            .get(name)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("no sidecar '{name}'")))?;
+
+        let start = (offset as usize).min(content.len());
+        let end = start.saturating_add(len).min(content.len());
+        Ok(content[start..end].to_vec())
+    }
+
+    fn write_sidecar(&mut self, name: &str, content: &[u8]) -> std::io::Result<()> {
+        self.data().sidecars.insert(name.to_string(), content.to_vec());
+        Ok(())
+    }
+
+    fn append_sidecar(&mut self, name: &str, content: &[u8]) -> std::io::Result<()> {
+        self.data().sidecars.entry(name.to_string()).or_default().extend_from_slice(content);
+        Ok(())
+    }
+}
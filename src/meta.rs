@@ -1,9 +1,12 @@
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Meta {
     pub version: u64,
     pub read_pointer: u64,
     pub write_pointer: u64,
     pub total_records_added: u64,
+    /// Active records before the write pointer. `None` when it isn't known,
+    /// e.g. on storages written before it was kept, until it's counted.
+    pub active_records: Option<u64>,
 }
 
 impl Meta {
@@ -13,13 +16,10 @@ impl Meta {
             read_pointer,
             write_pointer,
             total_records_added,
+            active_records: None,
         }
     }
 
-    pub fn default() -> Meta {
-        Self::new(0, 0, 0, 0)
-    }
-
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::<u8>::with_capacity(Meta::size());
 
@@ -27,10 +27,12 @@ impl Meta {
         bytes.extend_from_slice(&self.read_pointer.to_le_bytes());
         bytes.extend_from_slice(&self.write_pointer.to_le_bytes());
         bytes.extend_from_slice(&self.total_records_added.to_le_bytes());
+        bytes.extend_from_slice(&self.active_records.unwrap_or(Self::UNKNOWN).to_le_bytes());
 
         bytes
     }
 
+    /// Reads either layout; `LEGACY_SIZE` bytes leave the active count unknown.
     pub fn from_bytes(bytes: &[u8]) -> Self {
         // TODO: add error handling
 
@@ -38,11 +40,26 @@ impl Meta {
         let read_pointer= u64::from_le_bytes(bytes[8..16].try_into().unwrap());
         let write_pointer = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
         let total_records_added = u64::from_le_bytes(bytes[24..32].try_into().unwrap());
+        let active_records = bytes
+            .get(32..40)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .filter(|count| *count != Self::UNKNOWN);
 
-        Self { version, read_pointer, write_pointer, total_records_added }
+        Self { version, read_pointer, write_pointer, total_records_added, active_records }
     }
 
     pub fn is_empty(&self) -> bool { self.read_pointer == self.write_pointer }
 
-    pub const fn size() -> usize { 4 * size_of::<u64>() }
+    pub const fn size() -> usize { 5 * size_of::<u64>() }
+
+    /// Size of the meta before the active count was added to it.
+    pub const LEGACY_SIZE: usize = 4 * size_of::<u64>();
+
+    const UNKNOWN: u64 = u64::MAX;
+}
+
+impl Default for Meta {
+    fn default() -> Self {
+        Self { active_records: Some(0), ..Self::new(0, 0, 0, 0) }
+    }
 }
@@ -1,9 +1,14 @@
 use std::{
     fs::{File, OpenOptions},
     io::{ErrorKind, Read, Seek, Write},
+    path::Path,
+    time::Duration,
 };
 
-use crate::meta::Meta;
+use crate::{
+    file_lock::{FileLock, LockKind},
+    meta::Meta,
+};
 
 pub struct MetaStore {
     file: File,
@@ -11,11 +16,12 @@ pub struct MetaStore {
 }
 
 impl MetaStore {
-    pub fn open(path: &str) -> std::io::Result<Self> {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
         match OpenOptions::new().read(true).write(true).open(path) {
             Ok(file) => Ok(Self { file, meta: None }),
             Err(e) if e.kind() == ErrorKind::NotFound => {
                 let mut file = File::create_new(path)?;
+                let _lock = FileLock::acquire(&file, LockKind::Exclusive, FileLock::DEFAULT_TIMEOUT)?;
                 file.write_all(&Meta::default().to_bytes())?;
                 Ok(Self { file, meta: None })
             }
@@ -28,20 +34,38 @@ impl MetaStore {
             return Ok(meta);
         }
 
-        let mut buffer: [u8; Meta::size()] = [0; Meta::size()];
+        // Files written before the active count was kept are shorter
+        let mut buffer = Vec::with_capacity(Meta::size());
         self.file.seek(std::io::SeekFrom::Start(0))?;
-        self.file.read_exact(&mut buffer)?;
+        (&mut self.file).take(Meta::size() as u64).read_to_end(&mut buffer)?;
+        if buffer.len() < Meta::LEGACY_SIZE {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
         self.meta = Some(Meta::from_bytes(&buffer));
 
         Ok(self.meta.unwrap())
     }
 
+    /// Locks the meta file, which guards the whole storage, and drops the
+    /// cached meta since another process may have changed it.
+    pub fn lock(&mut self, kind: LockKind, timeout: Duration) -> std::io::Result<FileLock> {
+        let lock = FileLock::acquire(&self.file, kind, timeout)?;
+        self.meta = None;
+
+        Ok(lock)
+    }
+
     pub fn update(&mut self, meta: Meta) -> std::io::Result<()> {
         self.file.seek(std::io::SeekFrom::Start(0))?;
         self.file.write_all(&meta.to_bytes())?;
-        self.file.sync_all()?;
         self.meta = Some(meta);
 
         Ok(())
     }
+
+    /// Meta is rewritten in place, so `sync_data` is enough. It also covers
+    /// the size change when a legacy meta is first rewritten.
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.file.sync_data()
+    }
 }
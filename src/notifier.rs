@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, OnceLock, Weak},
+    task::Waker,
+    time::Duration,
+};
+
+/// Wakes in-process waiters when a storage gets new records.
+///
+/// Every `Storage` opened on the same files shares one notifier, so a save
+/// through one handle wakes waiters on all of them. Saves from other
+/// processes are not seen here and have to be picked up by polling.
+pub struct Notifier {
+    generation: Mutex<u64>,
+    changed: Condvar,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self {
+            generation: Mutex::new(0),
+            changed: Condvar::new(),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the shared notifier for the storage whose meta file is at `path`.
+    pub fn for_path(path: &Path) -> Arc<Notifier> {
+        static REGISTRY: OnceLock<Mutex<HashMap<PathBuf, Weak<Notifier>>>> = OnceLock::new();
+
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut registry = REGISTRY
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        if let Some(notifier) = registry.get(&key).and_then(Weak::upgrade) {
+            return notifier;
+        }
+
+        registry.retain(|_, notifier| notifier.strong_count() > 0);
+
+        let notifier = Arc::new(Notifier::new());
+        registry.insert(key, Arc::downgrade(&notifier));
+        notifier
+    }
+
+    pub fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn notify(&self) {
+        *self.generation.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.changed.notify_all();
+
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap_or_else(|e| e.into_inner()));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Blocks until the generation moves past `seen` or `timeout` runs out.
+    pub fn wait(&self, seen: u64, timeout: Duration) {
+        let generation = self.generation.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = self
+            .changed
+            .wait_timeout_while(generation, timeout, |generation| *generation == seen);
+    }
+
+    pub fn register_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// How often waiters re-check the files for saves made by other processes.
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+
+    #[test]
+    fn same_path_shares_notifier() {
+        let path = Path::new("re-queue-notifier-test");
+        let first = Notifier::for_path(path);
+        let second = Notifier::for_path(path);
+
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn notify_wakes_waiter() {
+        let notifier = Arc::new(Notifier::new());
+        let seen = notifier.generation();
+
+        let waiter = {
+            let notifier = notifier.clone();
+            thread::spawn(move || {
+                let started_at = Instant::now();
+                notifier.wait(seen, Duration::from_secs(10));
+                started_at.elapsed()
+            })
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        notifier.notify();
+
+        assert!(waiter.join().unwrap() < Duration::from_secs(10));
+    }
+}
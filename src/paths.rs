@@ -0,0 +1,136 @@
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// Locations of the storage directory and the config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoragePaths {
+    pub storage_dir: PathBuf,
+    pub config_path: PathBuf,
+}
+
+impl StoragePaths {
+    /// Keeps everything under one directory: `<root>/storage` and `<root>/config`.
+    pub fn from_root(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        Self {
+            storage_dir: root.join(Self::STORAGE_DIR_NAME),
+            config_path: root.join(Self::CONFIG_FILE_NAME),
+        }
+    }
+
+    /// Picks the locations to use, in order of precedence: an explicit root
+    /// (e.g. from a CLI flag), `RE_QUEUE_HOME`, then the XDG data and config
+    /// directories. Falls back to the working directory when none is known,
+    /// or when it holds what an older version left there and the XDG
+    /// directories have no config yet.
+    pub fn resolve(root: Option<PathBuf>) -> Self {
+        Self::resolve_with(root, |name| env::var_os(name))
+    }
+
+    /// `resolve`, reading variables through `var`.
+    fn resolve_with(root: Option<PathBuf>, var: impl Fn(&str) -> Option<OsString>) -> Self {
+        if let Some(root) = root {
+            return Self::from_root(root);
+        }
+
+        // A relative home is taken from the working directory, like `--home`
+        if let Some(home) = var(Self::HOME_ENV).filter(|home| !home.is_empty()) {
+            let home = PathBuf::from(home);
+            return Self::from_root(env::current_dir().map(|dir| dir.join(&home)).unwrap_or(home));
+        }
+
+        match Self::xdg_with(var) {
+            Some(xdg) if xdg.config_path.exists() || !Self::working_directory().has_files() => xdg,
+            _ => Self::working_directory(),
+        }
+    }
+
+    /// The XDG data and config directories, if they're known.
+    pub fn xdg() -> Option<Self> {
+        Self::xdg_with(|name| env::var_os(name))
+    }
+
+    fn xdg_with(var: impl Fn(&str) -> Option<OsString>) -> Option<Self> {
+        // Relative values are ignored, as the XDG spec asks
+        let xdg_path = |name| var(name).map(PathBuf::from).filter(|path| path.is_absolute());
+
+        let user_home = var("HOME").filter(|home| !home.is_empty()).map(PathBuf::from);
+        let data_home = xdg_path("XDG_DATA_HOME")
+            .or_else(|| user_home.as_ref().map(|home| home.join(".local").join("share")));
+        let config_home = xdg_path("XDG_CONFIG_HOME")
+            .or_else(|| user_home.as_ref().map(|home| home.join(".config")));
+
+        Some(Self {
+            storage_dir: data_home?.join(Self::APP_DIR_NAME).join(Self::STORAGE_DIR_NAME),
+            config_path: config_home?.join(Self::APP_DIR_NAME).join(Self::CONFIG_FILE_NAME),
+        })
+    }
+
+    /// Where versions before the XDG directories kept everything.
+    pub fn working_directory() -> Self {
+        Self::from_root(".")
+    }
+
+    /// Whether both the config and the storage directory exist.
+    pub fn has_files(&self) -> bool {
+        self.config_path.is_file() && self.storage_dir.is_dir()
+    }
+
+    /// Log of what was done to the storages, next to them.
+    pub fn audit_log_path(&self) -> PathBuf {
+        self.storage_dir.join(Self::AUDIT_LOG_FILE_NAME)
+    }
+
+    pub const HOME_ENV: &str = "RE_QUEUE_HOME";
+    const APP_DIR_NAME: &str = "re-queue";
+    const STORAGE_DIR_NAME: &str = "storage";
+    const CONFIG_FILE_NAME: &str = "config";
+    const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_files_needs_config_and_storage_dir() {
+        let root = env::temp_dir().join(format!("re-queue-paths-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let paths = StoragePaths::from_root(&root);
+
+        std::fs::create_dir_all(&paths.storage_dir).unwrap();
+        assert!(!paths.has_files());
+
+        std::fs::write(&paths.config_path, "").unwrap();
+        assert!(paths.has_files());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn relative_home_is_taken_from_the_working_directory() {
+        let var = |name: &str| match name {
+            StoragePaths::HOME_ENV => Some(OsString::from("queues")),
+            "XDG_DATA_HOME" => Some(OsString::from("relative/data")),
+            "HOME" => Some(OsString::from("/home/ann")),
+            _ => None,
+        };
+
+        let paths = StoragePaths::resolve_with(None, var);
+        assert_eq!(paths.config_path, env::current_dir().unwrap().join("queues/config"));
+
+        let xdg = StoragePaths::xdg_with(var).unwrap();
+        assert_eq!(xdg.storage_dir, PathBuf::from("/home/ann/.local/share/re-queue/storage"));
+    }
+
+    #[test]
+    fn explicit_root_wins() {
+        let paths = StoragePaths::resolve(Some(PathBuf::from("/tmp/queues")));
+
+        assert_eq!(paths.storage_dir, PathBuf::from("/tmp/queues/storage"));
+        assert_eq!(paths.config_path, PathBuf::from("/tmp/queues/config"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{notifier::Notifier, storage::StorageError};
+
+/// Future returned by `pick_async`, resolving like `pick_blocking`.
+///
+/// Runtime agnostic: in-process saves wake it through the notifier, and a
+/// small ticker thread wakes it periodically to poll for saves made by other
+/// processes and to notice the deadline.
+pub struct PickFuture<F> {
+    try_pick: F,
+    notifier: Arc<Notifier>,
+    deadline: Instant,
+    ticker: Option<Ticker>,
+}
+
+impl<F> PickFuture<F>
+where
+    F: FnMut() -> Result<String, StorageError> + Unpin,
+{
+    pub(crate) fn new(notifier: Arc<Notifier>, timeout: Duration, try_pick: F) -> Self {
+        Self {
+            try_pick,
+            notifier,
+            deadline: Instant::now() + timeout,
+            ticker: None,
+        }
+    }
+}
+
+impl<F> Future for PickFuture<F>
+where
+    F: FnMut() -> Result<String, StorageError> + Unpin,
+{
+    type Output = Result<String, StorageError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let seen = this.notifier.generation();
+
+        match (this.try_pick)() {
+            Err(StorageError::Empty) => {}
+            result => return Poll::Ready(result),
+        }
+
+        if Instant::now() >= this.deadline {
+            return Poll::Ready(Err(StorageError::Empty));
+        }
+
+        this.notifier.register_waker(cx.waker());
+        this.ticker
+            .get_or_insert_with(|| Ticker::start(this.deadline))
+            .set_waker(cx.waker());
+
+        // A save may have landed between the pick and registering the waker
+        if this.notifier.generation() != seen {
+            cx.waker().wake_by_ref();
+        }
+
+        Poll::Pending
+    }
+}
+
+struct Ticker {
+    waker: Arc<Mutex<Option<Waker>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Ticker {
+    fn start(deadline: Instant) -> Self {
+        let waker: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        {
+            let waker = waker.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    thread::sleep(remaining.min(Notifier::POLL_INTERVAL));
+
+                    if let Some(waker) = waker.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+                        waker.wake_by_ref();
+                    }
+
+                    if remaining.is_zero() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self { waker, stopped }
+    }
+
+    fn set_waker(&self, waker: &Waker) {
+        *self.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(waker.clone());
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Weak, task::Wake, thread::Thread};
+
+    use super::*;
+    use crate::{memory_backend::MemoryBackend, shared::SharedStorage, storage::Storage};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn save_from_another_thread_resolves_pick() {
+        let backend = MemoryBackend::new();
+        let mut storage = Storage::with_backend(Box::new(backend.clone()));
+
+        let saver = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            Storage::with_backend(Box::new(backend)).save("value".to_string()).unwrap();
+        });
+
+        assert_eq!(block_on(storage.pick_async(Duration::from_secs(5))).unwrap(), "value");
+        saver.join().unwrap();
+    }
+
+    #[test]
+    fn shared_pick_resolves_or_times_out() {
+        let shared = SharedStorage::new(Storage::in_memory());
+
+        let started = Instant::now();
+        assert!(matches!(block_on(shared.pick_async(Duration::from_millis(50))), Err(StorageError::Empty)));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let saver = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                shared.save("value".to_string()).unwrap();
+            })
+        };
+
+        assert_eq!(block_on(shared.pick_async(Duration::from_secs(5))).unwrap(), "value");
+        saver.join().unwrap();
+    }
+
+    #[test]
+    fn dropping_the_future_stops_the_ticker() {
+        let mut storage = Storage::in_memory();
+        let mut future = Box::pin(storage.pick_async(Duration::from_secs(60)));
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+
+        assert!(future.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+        let stopped: Weak<AtomicBool> = Arc::downgrade(&future.ticker.as_ref().unwrap().stopped);
+        drop(future);
+
+        // The thread lets go of its handle once it sees the stop, well before the deadline
+        let deadline = Instant::now() + Notifier::POLL_INTERVAL * 10;
+        while stopped.upgrade().is_some() {
+            assert!(Instant::now() < deadline, "ticker still running");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
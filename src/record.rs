@@ -1,4 +1,6 @@
-use crate::record_header::RecordHeader;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{checksum, record_header::RecordHeader};
 
 pub struct Record {
     pub meta: RecordHeader,
@@ -8,29 +10,38 @@ pub struct Record {
 impl Record {
     pub fn new(data: String, id: u64) -> Self {
         Self {
-            meta: RecordHeader::new(data.len() as u64, id),
+            meta: RecordHeader::new(data.len() as u64, id, checksum::crc32(data.as_bytes()), Self::now()),
             data,
         }
     }
 
-    pub fn from_bytes(meta: RecordHeader, bytes: &[u8]) -> Self {
-        // TODO: add error handling
+    /// Fails with `InvalidData` when the payload isn't UTF-8, which means
+    /// the data file is damaged.
+    pub fn from_bytes(meta: RecordHeader, bytes: &[u8]) -> std::io::Result<Self> {
+        let data = String::from_utf8(bytes.to_vec())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
-        let data = String::from_utf8(bytes.to_vec()).unwrap();
-        Self {
+        Ok(Self {
             meta,
             data,
-        }
+        })
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buffer = Vec::<u8>::new();
 
         buffer.extend_from_slice(&self.meta.to_bytes());
-        buffer.extend_from_slice(&self.data.as_bytes());
+        buffer.extend_from_slice(self.data.as_bytes());
 
         buffer
     }
 
+    /// Milliseconds since the Unix epoch, as kept in record headers.
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+
     pub fn size(&self) -> u64 { self.meta.get_content_size() + RecordHeader::size() as u64 }
 }
@@ -2,12 +2,27 @@ pub struct RecordHeader {
     is_active: bool,
     content_size: u64,
     id: u64,
-    reserved: [u8; 32],
+    /// `MAGIC` on records written since framing was added, zeroes before.
+    magic: [u8; 4],
+    /// CRC-32 of the payload, zero on unframed records.
+    checksum: u32,
+    /// Milliseconds since the Unix epoch, zero on records from before
+    /// timestamps were kept.
+    created_at: u64,
+    reserved: [u8; 16],
 }
 
 impl RecordHeader {
-    pub fn new(content_size: u64, id: u64) -> Self {
-        Self { is_active: true, content_size, id, reserved: [0; 32] }
+    pub fn new(content_size: u64, id: u64, checksum: u32, created_at: u64) -> Self {
+        Self {
+            is_active: true,
+            content_size,
+            id,
+            magic: Self::MAGIC,
+            checksum,
+            created_at,
+            reserved: [0; 16],
+        }
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Self {
@@ -15,12 +30,18 @@ impl RecordHeader {
         let is_active = bytes[0] != 0;
         let content_size = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
         let id = u64::from_le_bytes(bytes[9..17].try_into().unwrap());
-        let reserved = bytes[17..49].try_into().unwrap();
+        let magic = bytes[17..21].try_into().unwrap();
+        let checksum = u32::from_le_bytes(bytes[21..25].try_into().unwrap());
+        let created_at = u64::from_le_bytes(bytes[25..33].try_into().unwrap());
+        let reserved = bytes[33..49].try_into().unwrap();
 
         Self {
             is_active,
             content_size,
             id,
+            magic,
+            checksum,
+            created_at,
             reserved,
         }
     }
@@ -31,13 +52,50 @@ impl RecordHeader {
         buffer.extend_from_slice(&[self.is_active as u8]);
         buffer.extend_from_slice(&self.content_size.to_le_bytes());
         buffer.extend_from_slice(&self.id.to_le_bytes());
+        buffer.extend_from_slice(&self.magic);
+        buffer.extend_from_slice(&self.checksum.to_le_bytes());
+        buffer.extend_from_slice(&self.created_at.to_le_bytes());
         buffer.extend_from_slice(&self.reserved);
 
         buffer
     }
 
+    pub fn is_active(&self) -> bool { self.is_active }
     pub fn get_content_size(&self) -> u64 { self.content_size }
     pub fn get_id(&self) -> u64 { self.id }
+    pub fn get_checksum(&self) -> u32 { self.checksum }
+
+    /// When the record was saved, in milliseconds since the Unix epoch, if known.
+    pub fn get_created_at(&self) -> Option<u64> { (self.created_at != 0).then_some(self.created_at) }
+
+    /// Whether the header carries the magic and checksum. Records written
+    /// before framing existed don't, and can only be checked loosely.
+    pub fn is_framed(&self) -> bool { self.magic == Self::MAGIC }
+
+    /// Where the record starting at `pointer` ends, or an `InvalidData` error
+    /// when that's past `data_len`. The size comes from the file, so it's
+    /// checked before anything is allocated for the payload.
+    pub fn record_end(&self, pointer: u64, data_len: u64) -> std::io::Result<u64> {
+        pointer
+            .checked_add(Self::size() as u64)
+            .and_then(|end| end.checked_add(self.content_size))
+            .filter(|end| *end <= data_len)
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("record at {pointer} runs past the data"))
+            })
+    }
+
+    /// Neither framed nor an old unframed header, so not a header at all.
+    pub fn is_garbled(&self) -> bool {
+        !self.is_framed() && (self.magic != [0; 4] || self.checksum != 0)
+    }
 
     pub const fn size() -> usize { 2 * size_of::<u64>() + 1 + 32 }
+
+    pub const ACTIVE_FLAG_OFFSET: u64 = 0;
+
+    /// Marks the start of a record, so a reader can find the next one after
+    /// damaged bytes.
+    pub const MAGIC: [u8; 4] = *b"RQr1";
+    pub const MAGIC_OFFSET: usize = 17;
 }
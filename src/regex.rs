@@ -0,0 +1,446 @@
+//! Small regular expressions for searching records.
+//!
+//! Supports literals, `.`, classes like `[a-z]` and `[^0-9]`, the escapes
+//! `\d \w \s` (and their negations `\D \W \S`), anchors `^` and `$`, groups,
+//! alternation and the quantifiers `*`, `+` and `?`. Lazy quantifiers like
+//! `*?` are rejected, `{n,m}` is taken literally, and groups nest at most
+//! `MAX_NESTING` deep. Matching simulates all paths through the pattern at
+//! once, so it takes time linear in the input whatever the pattern.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    /// Character offset into the pattern.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern at {}: {}", self.position, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(from, to) => (*from..=*to).contains(&c),
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Word => c.is_alphanumeric() || c == '_',
+            ClassItem::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let found = if ignore_case {
+            c.to_lowercase().chain(c.to_uppercase()).any(|c| self.items.iter().any(|item| item.matches(c)))
+        } else {
+            self.items.iter().any(|item| item.matches(c))
+        };
+
+        found != self.negated
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, many: bool },
+}
+
+#[derive(Debug, Clone)]
+enum Instruction {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Deepest group nesting accepted, which keeps the recursive parser and
+/// compiler well within the stack.
+pub const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Instruction>,
+    ignore_case: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self, RegexError> {
+        let mut parser = Parser { chars: pattern.chars().collect(), position: 0, depth: 0 };
+        let node = parser.alternation()?;
+
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unmatched ')'"));
+        }
+
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Instruction::Match);
+
+        Ok(Self { program, ignore_case })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut seen = vec![usize::MAX; self.program.len()];
+
+        for position in 0..=chars.len() {
+            // Unanchored: a match may start at every position
+            self.add_thread(&mut current, &mut seen, 0, position, chars.len());
+
+            for &pc in &current {
+                let c = chars.get(position).copied();
+                let advances = match (&self.program[pc], c) {
+                    (Instruction::Match, _) => return true,
+                    (Instruction::Char(expected), Some(c)) => self.same_char(*expected, c),
+                    (Instruction::Any, Some(c)) => c != '\n',
+                    (Instruction::Class(class), Some(c)) => class.matches(c, self.ignore_case),
+                    _ => false,
+                };
+
+                if advances {
+                    self.add_thread(&mut next, &mut seen, pc + 1, position + 1, chars.len());
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        false
+    }
+
+    /// Follows jumps, splits and anchors from `pc`, adding the instructions
+    /// that consume input (or match) to `threads`. Long runs of `?` chain
+    /// splits, so this keeps its own stack rather than recursing.
+    fn add_thread(&self, threads: &mut Vec<usize>, seen: &mut [usize], pc: usize, position: usize, len: usize) {
+        let mut pending = vec![pc];
+
+        while let Some(pc) = pending.pop() {
+            if seen[pc] == position {
+                continue;
+            }
+            seen[pc] = position;
+
+            match self.program[pc] {
+                Instruction::Jump(target) => pending.push(target),
+                Instruction::Split(first, second) => pending.extend([second, first]),
+                Instruction::Start if position == 0 => pending.push(pc + 1),
+                Instruction::End if position == len => pending.push(pc + 1),
+                Instruction::Start | Instruction::End => {}
+                _ => threads.push(pc),
+            }
+        }
+    }
+
+    fn same_char(&self, expected: char, c: char) -> bool {
+        expected == c || (self.ignore_case && expected.to_lowercase().eq(c.to_lowercase()))
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Instruction>) {
+    match node {
+        Node::Char(c) => program.push(Instruction::Char(*c)),
+        Node::Any => program.push(Instruction::Any),
+        Node::Class(class) => program.push(Instruction::Class(class.clone())),
+        Node::Start => program.push(Instruction::Start),
+        Node::End => program.push(Instruction::End),
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+        Node::Alternate(branches) => {
+            let mut jumps = Vec::new();
+
+            for (index, branch) in branches.iter().enumerate() {
+                if index + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Instruction::Split(split + 1, 0));
+                    compile(branch, program);
+                    jumps.push(program.len());
+                    program.push(Instruction::Jump(0));
+                    program[split] = Instruction::Split(split + 1, program.len());
+                } else {
+                    compile(branch, program);
+                }
+            }
+
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Instruction::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, many } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+
+            match (*min, *many) {
+                // `?`
+                (0, false) => {
+                    let split = program.len();
+                    program.push(Instruction::Split(split + 1, 0));
+                    compile(node, program);
+                    program[split] = Instruction::Split(split + 1, program.len());
+                }
+                // `*`, or the rest of `+`
+                (_, true) => {
+                    let split = program.len();
+                    program.push(Instruction::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Instruction::Jump(split));
+                    program[split] = Instruction::Split(split + 1, program.len());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// Groups open at `position`.
+    depth: usize,
+}
+
+impl Parser {
+    fn alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.concatenation()?];
+        while self.eat('|') {
+            branches.push(self.concatenation()?);
+        }
+
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alternate(branches) })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+
+        while let Some(&c) = self.chars.get(self.position) {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+
+        Ok(Node::Concat(nodes))
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, RegexError> {
+        let (min, many) = match self.chars.get(self.position) {
+            Some('*') => (0, true),
+            Some('+') => (1, true),
+            Some('?') => (0, false),
+            _ => return Ok(atom),
+        };
+        self.position += 1;
+
+        if matches!(atom, Node::Start | Node::End) {
+            return Err(self.error("nothing to repeat"));
+        }
+        if matches!(self.chars.get(self.position), Some('*' | '+' | '?')) {
+            return Err(self.error("nested quantifier"));
+        }
+
+        Ok(Node::Repeat { node: Box::new(atom), min, many })
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.chars[self.position];
+        self.position += 1;
+
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '(' => {
+                if self.depth == MAX_NESTING {
+                    self.position -= 1;
+                    return Err(self.error(&format!("groups nested deeper than {MAX_NESTING}")));
+                }
+
+                self.depth += 1;
+                let node = self.alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return Err(self.error("unclosed group"));
+                }
+                Ok(node)
+            }
+            '[' => self.class(),
+            '\\' => match self.escape()? {
+                Ok(c) => Ok(Node::Char(c)),
+                Err(class) => Ok(Node::Class(class)),
+            },
+            '*' | '+' | '?' => {
+                self.position -= 1;
+                Err(self.error("nothing to repeat"))
+            }
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+
+        loop {
+            let c = match self.chars.get(self.position) {
+                Some(']') if !items.is_empty() => {
+                    self.position += 1;
+                    break;
+                }
+                Some(&c) => c,
+                None => return Err(self.error("unclosed class")),
+            };
+            self.position += 1;
+
+            let from = if c == '\\' {
+                match self.escape()? {
+                    Ok(c) => c,
+                    Err(class) => {
+                        items.extend(class.items);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+
+            let is_range = self.chars.get(self.position) == Some(&'-')
+                && self.chars.get(self.position + 1).is_some_and(|&c| c != ']');
+            if !is_range {
+                items.push(ClassItem::Range(from, from));
+                continue;
+            }
+
+            self.position += 1;
+            let mut to = self.chars[self.position];
+            self.position += 1;
+            if to == '\\' {
+                to = self.escape()?.map_err(|_| self.error("class in range"))?;
+            }
+            if to < from {
+                return Err(self.error("range out of order"));
+            }
+            items.push(ClassItem::Range(from, to));
+        }
+
+        Ok(Node::Class(Class { items, negated }))
+    }
+
+    /// Reads what follows a backslash: a literal character, or a class for
+    /// the shorthand escapes.
+    fn escape(&mut self) -> Result<Result<char, Class>, RegexError> {
+        let c = *self.chars.get(self.position).ok_or_else(|| self.error("trailing backslash"))?;
+        self.position += 1;
+
+        let class = |item, negated| Err(Class { items: vec![item], negated });
+        Ok(match c {
+            'd' => class(ClassItem::Digit, false),
+            'D' => class(ClassItem::Digit, true),
+            'w' => class(ClassItem::Word, false),
+            'W' => class(ClassItem::Word, true),
+            's' => class(ClassItem::Space, false),
+            'S' => class(ClassItem::Space, true),
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            c if c.is_alphanumeric() => {
+                self.position -= 1;
+                return Err(self.error(&format!("unknown escape '\\{c}'")));
+            }
+            c => Ok(c),
+        })
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.chars.get(self.position) == Some(&c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn error(&self, message: &str) -> RegexError {
+        RegexError { position: self.position, message: message.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern, false).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn matches_common_patterns() {
+        assert!(is_match("b+c", "abbbcd"));
+        assert!(is_match("^ticket-\\d+$", "ticket-42"));
+        assert!(!is_match("^ticket-\\d+$", "ticket-42a"));
+        assert!(is_match("(cat|dog)s?$", "hot dogs"));
+        assert!(is_match("[^a-z ]", "lower Upper"));
+        assert!(!is_match("[^a-z ]", "all lower"));
+        assert!(is_match("colou?r", "color"));
+        assert!(is_match("", "anything"));
+        assert!(Regex::new("TODO", true).unwrap().is_match("a todo item"));
+    }
+
+    #[test]
+    fn pathological_pattern_stays_fast() {
+        let text = "a".repeat(5000);
+        assert!(!is_match("(a*)*b", &text));
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        assert_eq!(Regex::new("(ab", false).unwrap_err().message, "unclosed group");
+        assert_eq!(Regex::new("a)", false).unwrap_err().position, 1);
+        assert!(Regex::new("*a", false).is_err());
+        assert!(Regex::new("[z-a]", false).is_err());
+        assert_eq!(Regex::new("a*?", false).unwrap_err().message, "nested quantifier");
+        assert!(is_match("a{2}", "a{2}"));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(is_match(&nested(MAX_NESTING), "a"));
+        assert_eq!(Regex::new(&nested(MAX_NESTING + 1), false).unwrap_err().position, MAX_NESTING);
+        assert!(Regex::new(&"(".repeat(100_000), false).is_err());
+        assert!(is_match(&"a?".repeat(100_000), ""));
+    }
+}
@@ -0,0 +1,81 @@
+//! Picking out records by their value, id and state.
+
+use std::ops::RangeInclusive;
+
+use crate::{
+    record::Record,
+    regex::{Regex, RegexError},
+};
+
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// Every value.
+    All,
+    Substring { text: String, ignore_case: bool },
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn substring(text: &str, ignore_case: bool) -> Self {
+        let text = if ignore_case { text.to_lowercase() } else { text.to_string() };
+        Matcher::Substring { text, ignore_case }
+    }
+
+    pub fn regex(pattern: &str, ignore_case: bool) -> Result<Self, RegexError> {
+        Ok(Matcher::Regex(Regex::new(pattern, ignore_case)?))
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        match self {
+            Matcher::All => true,
+            Matcher::Substring { text, ignore_case: true } => value.to_lowercase().contains(text.as_str()),
+            Matcher::Substring { text, ignore_case: false } => value.contains(text.as_str()),
+            Matcher::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// What `Storage::search` looks for. Unset filters let everything through.
+#[derive(Debug, Clone)]
+pub struct SearchFilter {
+    pub matcher: Matcher,
+    pub ids: Option<RangeInclusive<u64>>,
+    /// Only active records, or only removed ones.
+    pub is_active: Option<bool>,
+}
+
+impl Default for SearchFilter {
+    fn default() -> Self {
+        Self { matcher: Matcher::All, ids: None, is_active: None }
+    }
+}
+
+impl SearchFilter {
+    pub fn matches(&self, record: &Record) -> bool {
+        self.is_active.is_none_or(|is_active| is_active == record.meta.is_active())
+            && self.ids.as_ref().is_none_or(|ids| ids.contains(&record.meta.get_id()))
+            && self.matcher.is_match(&record.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_combine() {
+        let record = Record::new("Call Bob about ticket 42".to_string(), 7);
+
+        let mut filter = SearchFilter { matcher: Matcher::substring("bob", true), ..SearchFilter::default() };
+        assert!(filter.matches(&record));
+
+        filter.ids = Some(1..=5);
+        assert!(!filter.matches(&record));
+
+        filter = SearchFilter { matcher: Matcher::substring("bob", false), ..SearchFilter::default() };
+        assert!(!filter.matches(&record));
+
+        filter = SearchFilter { matcher: Matcher::regex("ticket \\d+$", false).unwrap(), is_active: Some(true), ids: Some(7..=7) };
+        assert!(filter.matches(&record));
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    durability::Durability,
+    notifier::Notifier,
+    record::Record,
+    storage::{Storage, StorageError},
+    storage_manager::{StorageManager, StorageManagerError},
+};
+
+#[cfg(feature = "async")]
+use crate::pick_future::PickFuture;
+
+/// Cloneable `Send + Sync` handle to a `Storage`.
+///
+/// Calls are serialized in-process by a mutex, and every storage operation
+/// still takes the on-disk lock, so handles also cooperate with other
+/// processes using the same files.
+#[derive(Clone)]
+pub struct SharedStorage {
+    inner: Arc<Mutex<Storage>>,
+}
+
+impl SharedStorage {
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    pub fn save(&self, value: String) -> Result<(), StorageError> {
+        self.lock().save(value)
+    }
+
+    pub fn pick(&self) -> Result<String, StorageError> {
+        self.lock().pick()
+    }
+
+    /// Like `Storage::pick_blocking`, but other threads can use the storage
+    /// while this one waits.
+    pub fn pick_blocking(&self, timeout: Duration) -> Result<String, StorageError> {
+        let notifier = self.lock().get_notifier();
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let seen = notifier.generation();
+
+            match self.pick() {
+                Err(StorageError::Empty) => {}
+                result => return result,
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(StorageError::Empty);
+            }
+
+            notifier.wait(seen, remaining.min(Notifier::POLL_INTERVAL));
+        }
+    }
+
+    /// Async counterpart of `pick_blocking`.
+    #[cfg(feature = "async")]
+    pub fn pick_async(&self, timeout: Duration) -> PickFuture<impl FnMut() -> Result<String, StorageError> + use<>> {
+        let notifier = self.lock().get_notifier();
+        let shared = self.clone();
+        PickFuture::new(notifier, timeout, move || shared.pick())
+    }
+
+    pub fn move_next(&self) -> Result<(), StorageError> {
+        self.lock().move_next()
+    }
+
+    pub fn get_all(&self) -> Result<Vec<Record>, StorageError> {
+        self.lock().get_all()
+    }
+
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.lock().flush()
+    }
+
+    pub fn get_durability(&self) -> Durability {
+        self.lock().get_durability()
+    }
+
+    pub fn set_durability(&self, durability: Durability) {
+        self.lock().set_durability(durability)
+    }
+
+    /// Runs several operations without other threads interleaving.
+    pub fn with<T>(&self, f: impl FnOnce(&mut Storage) -> T) -> T {
+        f(&mut self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Storage> {
+        // A panic mid-operation leaves nothing half-applied in memory that the
+        // next operation relies on, so poisoning is ignored
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Cloneable `Send + Sync` handle to a `StorageManager`.
+#[derive(Clone)]
+pub struct SharedStorageManager {
+    inner: Arc<Mutex<StorageManager>>,
+}
+
+impl SharedStorageManager {
+    pub fn new(storage_manager: StorageManager) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(storage_manager)),
+        }
+    }
+
+    pub fn create(&self, storage_name: &str) -> Result<(), StorageManagerError> {
+        self.lock().create(storage_name)
+    }
+
+    pub fn open(&self, storage_name: &str) -> Result<(), StorageManagerError> {
+        self.lock().open(storage_name)
+    }
+
+    pub fn get_active_storage_name(&self) -> Result<String, StorageManagerError> {
+        self.lock().get_active_storage_name()
+    }
+
+    pub fn get_list(&self) -> Vec<String> {
+        self.lock().get_list().to_vec()
+    }
+
+    pub fn has_storages(&self) -> bool {
+        self.lock().has_storages()
+    }
+
+    pub fn save(&self, value: String) -> Result<(), StorageManagerError> {
+        Ok(self.lock().get_active_storage()?.save(value)?)
+    }
+
+    pub fn pick(&self) -> Result<String, StorageManagerError> {
+        Ok(self.lock().get_active_storage()?.pick()?)
+    }
+
+    pub fn move_next(&self) -> Result<(), StorageManagerError> {
+        Ok(self.lock().get_active_storage()?.move_next()?)
+    }
+
+    pub fn get_all(&self) -> Result<Vec<Record>, StorageManagerError> {
+        Ok(self.lock().get_active_storage()?.get_all()?)
+    }
+
+    /// Runs several operations without other threads interleaving, e.g.
+    /// opening a storage and saving into it.
+    pub fn with<T>(&self, f: impl FnOnce(&mut StorageManager) -> T) -> T {
+        f(&mut self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StorageManager> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn handles_are_send_and_sync() {
+        assert_send_sync::<SharedStorage>();
+        assert_send_sync::<SharedStorageManager>();
+    }
+
+    #[test]
+    fn saves_from_many_threads_are_all_kept() {
+        let dir = std::env::temp_dir().join(format!("re-queue-shared-{}", std::process::id()));
+        let storage = Storage::new(&dir, "test.mt", "test.dt").unwrap();
+        let shared = SharedStorage::new(storage);
+
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for i in 0..10 {
+                        shared.save(format!("{worker}-{i}")).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let records = shared.get_all().unwrap();
+        assert_eq!(records.len(), 40);
+        assert!(records.iter().enumerate().all(|(i, r)| r.meta.get_id() == i as u64 + 1));
+
+        drop(shared);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pick_blocking_is_woken_by_save() {
+        let shared = SharedStorage::new(Storage::in_memory());
+
+        let waiter = {
+            let shared = shared.clone();
+            thread::spawn(move || shared.pick_blocking(Duration::from_secs(10)))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        shared.save("value".to_string()).unwrap();
+
+        assert_eq!(waiter.join().unwrap().unwrap(), "value");
+    }
+
+    #[test]
+    fn pick_blocking_times_out_on_empty_storage() {
+        let shared = SharedStorage::new(Storage::in_memory());
+
+        assert!(matches!(
+            shared.pick_blocking(Duration::from_millis(10)),
+            Err(StorageError::Empty)
+        ));
+    }
+}
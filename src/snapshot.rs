@@ -0,0 +1,87 @@
+//! Named points in time of a storage, kept in its `snapshots` sidecar.
+//!
+//! Records are only ever appended, so a snapshot is the meta at the time it
+//! was taken: everything before its write pointer is still in the data file.
+//! Removal flips flags in place though, so the records that were inactive
+//! back then are listed too.
+
+use crate::{
+    config_format::{self, Entry, ParseError, Position, Value},
+    meta::Meta,
+};
+
+pub const SIDECAR: &str = "snapshots";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub meta: Meta,
+    /// Pointers of the records before `meta.write_pointer` that were inactive.
+    pub inactive: Vec<u64>,
+}
+
+pub fn parse(content: &str) -> Result<Vec<Snapshot>, ParseError> {
+    let mut snapshots = Vec::new();
+
+    for table in config_format::parse(content)? {
+        let name = match table.path.as_slice() {
+            [] if table.entries.is_empty() => continue,
+            [section, name] if section == "snapshots" => name.clone(),
+            _ => return Err(ParseError::new(table.position, "expected a [snapshots.<name>] table")),
+        };
+
+        let mut snapshot = Snapshot { name, created_at: 0, meta: Meta::new(0, 0, 0, 0), inactive: Vec::new() };
+
+        for entry in &table.entries {
+            match entry.key.as_str() {
+                "created_at" => snapshot.created_at = integer(&entry.value, entry.value_position)?,
+                "version" => snapshot.meta.version = integer(&entry.value, entry.value_position)?,
+                "read_pointer" => snapshot.meta.read_pointer = integer(&entry.value, entry.value_position)?,
+                "write_pointer" => snapshot.meta.write_pointer = integer(&entry.value, entry.value_position)?,
+                "total_records_added" => {
+                    snapshot.meta.total_records_added = integer(&entry.value, entry.value_position)?
+                }
+                "inactive" => snapshot.inactive = integer_array(entry)?,
+                key => return Err(ParseError::new(entry.key_position, format!("unknown key '{key}'"))),
+            }
+        }
+
+        snapshots.push(snapshot);
+    }
+
+    Ok(snapshots)
+}
+
+pub fn serialize(snapshots: &[Snapshot]) -> String {
+    let mut result = String::new();
+
+    for snapshot in snapshots {
+        let inactive: Vec<String> = snapshot.inactive.iter().map(u64::to_string).collect();
+
+        result.push_str(&format!("[snapshots.{}]\n", config_format::key(&snapshot.name)));
+        result.push_str(&format!("created_at = {}\n", snapshot.created_at));
+        result.push_str(&format!("version = {}\n", snapshot.meta.version));
+        result.push_str(&format!("read_pointer = {}\n", snapshot.meta.read_pointer));
+        result.push_str(&format!("write_pointer = {}\n", snapshot.meta.write_pointer));
+        result.push_str(&format!("total_records_added = {}\n", snapshot.meta.total_records_added));
+        result.push_str(&format!("inactive = [{}]\n\n", inactive.join(", ")));
+    }
+
+    result
+}
+
+fn integer(value: &Value, position: Position) -> Result<u64, ParseError> {
+    match value {
+        Value::Integer(n) => u64::try_from(*n).map_err(|_| ParseError::new(position, "expected a positive integer")),
+        other => Err(ParseError::new(position, format!("expected an integer, found {}", other.type_name()))),
+    }
+}
+
+fn integer_array(entry: &Entry) -> Result<Vec<u64>, ParseError> {
+    match &entry.value {
+        Value::Array(items) => items.iter().map(|(value, position)| integer(value, *position)).collect(),
+        other => Err(ParseError::new(entry.value_position, format!("expected an array, found {}", other.type_name()))),
+    }
+}
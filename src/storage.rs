@@ -1,13 +1,55 @@
-use std::{fs, path::Path};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::Write,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    data_store::DataStore, meta_store::MetaStore, record::Record, record_header::RecordHeader,
+    audit::{Action, AuditLog},
+    backend::{BackendLock, FileBackend, StorageBackend},
+    durability::{Durability, SyncTracker},
+    export::{self, ExportFormat},
+    import::ImportReport,
+    index::{self, Index},
+    file_lock::{FileLock, LockKind},
+    integrity::{self, SalvageReport, Scan, VerifyReport},
+    journal::{self, Journal, JournalEntry, Operation},
+    memory_backend::MemoryBackend,
+    meta::Meta,
+    notifier::Notifier,
+    record::Record,
+    record_header::RecordHeader,
+    search::SearchFilter,
+    snapshot::{self, Snapshot},
 };
 
+#[cfg(feature = "async")]
+use crate::pick_future::PickFuture;
+
 #[derive(Debug)]
 pub enum StorageError {
     Io(std::io::Error),
     Empty,
+    Locked,
+    RecordNotFound(u64),
+    QuotaExceeded(u64),
+    ReadOnly,
+    SnapshotNotFound(String),
+    SnapshotAlreadyExists(String),
+    /// The data no longer holds what the snapshot points at, e.g. after a
+    /// repair cut it short.
+    SnapshotOutdated(String),
+    NothingToUndo,
+    NothingToRedo,
+    /// The record a journaled operation was about isn't where it was.
+    JournalOutdated,
+    NoIndex,
+    /// An import saved all of its records, then failed to bring the journal
+    /// or index up to date or to sync them.
+    AfterImport { added: u64, error: Box<StorageError> },
 }
 
 impl From<std::io::Error> for StorageError {
@@ -16,71 +58,1234 @@ impl From<std::io::Error> for StorageError {
     }
 }
 
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "storage i/o error: {e}"),
+            StorageError::Empty => write!(f, "storage is empty"),
+            StorageError::Locked => write!(f, "storage is in use by another process"),
+            StorageError::RecordNotFound(id) => write!(f, "record {id} not found"),
+            StorageError::QuotaExceeded(max_records) => write!(f, "storage is full, it holds at most {max_records} records"),
+            StorageError::ReadOnly => write!(f, "storage is read-only"),
+            StorageError::SnapshotNotFound(name) => write!(f, "snapshot '{name}' not found"),
+            StorageError::SnapshotAlreadyExists(name) => write!(f, "snapshot '{name}' already exists"),
+            StorageError::SnapshotOutdated(name) => write!(f, "snapshot '{name}' no longer matches the data"),
+            StorageError::NothingToUndo => write!(f, "nothing to undo"),
+            StorageError::NothingToRedo => write!(f, "nothing to redo"),
+            StorageError::JournalOutdated => write!(f, "journal no longer matches the data"),
+            StorageError::NoIndex => write!(f, "storage has no full-text index"),
+            StorageError::AfterImport { added, error } => write!(f, "imported {added} records, then failed: {error}"),
+        }
+    }
+}
+
 pub struct Storage {
-    meta_store: MetaStore,
-    data_store: DataStore,
+    backend: Box<dyn StorageBackend>,
+    sync_tracker: SyncTracker,
+    notifier: Arc<Notifier>,
+    max_records: Option<u64>,
+    read_only: bool,
+    journal_appends: usize,
+    audit_log: Option<(AuditLog, String)>,
+    /// The full-text index as last read, if there is one.
+    index: Option<Index>,
 }
 
 impl Storage {
-    pub fn new(dir_path: &str, meta_store_path: &str, data_store_path: &str) -> Result<Self, StorageError> {
+    pub fn new(dir_path: impl AsRef<Path>, meta_store_path: &str, data_store_path: &str) -> Result<Self, StorageError> {
+        let dir_path = dir_path.as_ref();
         fs::create_dir_all(dir_path)?;
 
-        let meta_store_full_path = Self::file_path(dir_path, meta_store_path);
-        let data_store_full_path = Self::file_path(dir_path, data_store_path);
+        let meta_store_full_path = dir_path.join(meta_store_path);
+        let data_store_full_path = dir_path.join(data_store_path);
+
+        Ok(Self::with_backend(Box::new(FileBackend::open(
+            &meta_store_full_path,
+            &data_store_full_path,
+        )?)))
+    }
+
+    /// Storage that lives only as long as the process, handy for tests.
+    pub fn in_memory() -> Self {
+        Self::with_backend(Box::new(MemoryBackend::new()))
+    }
+
+    pub fn with_backend(backend: Box<dyn StorageBackend>) -> Self {
+        let notifier = backend.notifier();
+
+        Self {
+            backend,
+            sync_tracker: SyncTracker::new(Durability::default()),
+            notifier,
+            max_records: None,
+            read_only: false,
+            journal_appends: 0,
+            audit_log: None,
+            index: None,
+        }
+    }
+
+    pub fn get_durability(&self) -> Durability {
+        self.sync_tracker.get_durability()
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.sync_tracker.set_durability(durability);
+    }
+
+    pub fn get_max_records(&self) -> Option<u64> {
+        self.max_records
+    }
+
+    /// Caps the number of active records. Saves check it against
+    /// `Meta::active_records`, which is only counted when it isn't known yet.
+    pub fn set_max_records(&mut self, max_records: Option<u64>) {
+        self.max_records = max_records;
+    }
+
+    /// Logs saves, picks, moves, removals, undos and redos from now on, under
+    /// `storage_name`.
+    pub fn set_audit_log(&mut self, audit_log: AuditLog, storage_name: &str) {
+        self.audit_log = Some((audit_log, storage_name.to_string()));
+    }
 
-        Ok(Self {
-            meta_store: MetaStore::open(meta_store_full_path.to_str().unwrap())?,
-            data_store: DataStore::open(data_store_full_path.to_str().unwrap())?,
-        })
+    /// Whether this is a view that refuses changes, like an opened snapshot.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
     }
 
     pub fn save(&mut self, value: String) -> Result<(), StorageError> {
-        let mut meta = self.meta_store.get()?;
-        let total_records_added = meta.total_records_added + 1;
-        let record = Record::new(value, total_records_added);
+        self.append(value, None)
+    }
 
-        meta.write_pointer += self.data_store.push(&record)?;
-        meta.total_records_added = total_records_added;
-        self.meta_store.update(meta)?;
+    /// Saves a record under an id taken from elsewhere, e.g. another
+    /// storage. Ids handed out by later saves continue after it.
+    pub fn save_with_id(&mut self, value: String, id: u64) -> Result<(), StorageError> {
+        self.append(value, Some(id))
+    }
 
-        Ok(())
+    fn append(&mut self, value: String, id: Option<u64>) -> Result<(), StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut meta = self.backend.get_meta()?;
+
+        if let Some(max_records) = self.max_records {
+            let active_records = match meta.active_records {
+                Some(active_records) => active_records,
+                None => self.count_active(&meta)?,
+            };
+            if active_records >= max_records {
+                return Err(StorageError::QuotaExceeded(max_records));
+            }
+            meta.active_records = Some(active_records);
+        }
+
+        let id = id.unwrap_or(meta.total_records_added + 1);
+        let record = Record::new(value, id);
+
+        let pointer = meta.write_pointer;
+        meta.write_pointer += self.backend.push(&record)?;
+        meta.total_records_added = meta.total_records_added.max(id);
+        meta.active_records = meta.active_records.map(|n| n + 1);
+        self.backend.update_meta(meta)?;
+
+        self.journal(JournalEntry {
+            operation: Operation::Save,
+            read_pointer_before: meta.read_pointer,
+            read_pointer_after: meta.read_pointer,
+            record: Some((pointer, id)),
+        })?;
+        self.audit(Action::Save, Some(id), "");
+        self.update_index(index::add_line(pointer, &record.data))?;
+
+        self.notifier.notify();
+
+        self.after_write()
     }
 
     pub fn pick(&mut self) -> Result<String, StorageError> {
-        let meta = self.meta_store.get()?;
-        if meta.is_empty() {
-            return Err(StorageError::Empty);
+        Ok(self.pick_record()?.data)
+    }
+
+    /// The record under the cursor, header included.
+    pub fn pick_record(&mut self) -> Result<Record, StorageError> {
+        let record = self.current_record()?;
+        self.audit(Action::Pick, Some(record.meta.get_id()), "");
+
+        Ok(record)
+    }
+
+    /// Like `pick_record`, for looking at the cursor without it counting as
+    /// a pick in the audit log.
+    pub(crate) fn current_record(&mut self) -> Result<Record, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        let meta = self.backend.get_meta()?;
+        match self.next_active(&meta, meta.read_pointer)? {
+            Some((_, record)) => Ok(record),
+            None => Err(StorageError::Empty),
         }
+    }
 
-        Ok(self.data_store.pick(meta.read_pointer)?.data)
+    /// Active record with the given id.
+    pub fn get_by_id(&mut self, id: u64) -> Result<Record, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        let meta = self.backend.get_meta()?;
+        match self.find_active_by_id(&meta, id)? {
+            Some((_, record)) => Ok(record),
+            None => Err(StorageError::RecordNotFound(id)),
+        }
+    }
+
+    /// Deactivates a record. The data stays in the file, but the record is
+    /// skipped from now on; if the cursor was on it, it moves to the next one.
+    pub fn remove(&mut self, id: u64) -> Result<(), StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut meta = self.backend.get_meta()?;
+        let (pointer, record) = self
+            .find_active_by_id(&meta, id)?
+            .ok_or(StorageError::RecordNotFound(id))?;
+
+        let read_pointer_before = meta.read_pointer;
+        let current = self.next_active(&meta, meta.read_pointer)?.map(|(p, _)| p);
+        self.backend.set_active(pointer, false)?;
+
+        if current == Some(pointer) {
+            meta.read_pointer = match self.next_active(&meta, pointer + record.size())? {
+                Some((next_pointer, _)) => next_pointer,
+                // Nothing left; the next saved record becomes current
+                None => meta.write_pointer,
+            };
+        }
+        meta.active_records = meta.active_records.map(|n| n.saturating_sub(1));
+        self.backend.update_meta(meta)?;
+
+        self.journal(JournalEntry {
+            operation: Operation::Remove,
+            read_pointer_before,
+            read_pointer_after: meta.read_pointer,
+            record: Some((pointer, id)),
+        })?;
+        self.audit(Action::Remove, Some(id), "");
+        self.update_index(index::remove_line(pointer))?;
+
+        self.after_write()
+    }
+
+    /// Like `pick`, but waits up to `timeout` for a record to arrive instead
+    /// of failing right away. Returns `StorageError::Empty` if none did.
+    ///
+    /// Saves made in this process wake the waiter immediately; saves from
+    /// other processes are noticed within `Notifier::POLL_INTERVAL`.
+    pub fn pick_blocking(&mut self, timeout: Duration) -> Result<String, StorageError> {
+        let notifier = self.notifier.clone();
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let seen = notifier.generation();
+
+            match self.pick() {
+                Err(StorageError::Empty) => {}
+                result => return result,
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(StorageError::Empty);
+            }
+
+            notifier.wait(seen, remaining.min(Notifier::POLL_INTERVAL));
+        }
+    }
+
+    /// Async counterpart of `pick_blocking`.
+    #[cfg(feature = "async")]
+    pub fn pick_async(&mut self, timeout: Duration) -> PickFuture<impl FnMut() -> Result<String, StorageError> + '_> {
+        let notifier = self.notifier.clone();
+        PickFuture::new(notifier, timeout, move || self.pick())
     }
 
     pub fn move_next(&mut self) -> Result<(), StorageError> {
-        let mut meta = self.meta_store.get()?;
+        let _lock = self.lock_for_write()?;
 
-        let record = self.data_store.pick(meta.read_pointer)?;
+        let mut meta = self.backend.get_meta()?;
 
-        let next_record_read_pointer = meta.read_pointer as usize
+        let (pointer, record) = self
+            .next_active(&meta, meta.read_pointer)?
+            .ok_or(StorageError::Empty)?;
+
+        let next_record_read_pointer = pointer as usize
             + RecordHeader::size()
             + record.meta.get_content_size() as usize;
 
-        if next_record_read_pointer < meta.write_pointer as usize {
-            meta.read_pointer = next_record_read_pointer as u64;
+        let next_record_read_pointer = if next_record_read_pointer < meta.write_pointer as usize {
+            next_record_read_pointer as u64
         } else {
-            meta.read_pointer = 0;
+            0
+        };
+
+        let read_pointer_before = meta.read_pointer;
+        meta.read_pointer = match self.next_active(&meta, next_record_read_pointer)? {
+            Some((next_pointer, _)) => next_pointer,
+            None => pointer,
+        };
+
+        self.backend.update_meta(meta)?;
+
+        self.journal(JournalEntry {
+            operation: Operation::MoveNext,
+            read_pointer_before,
+            read_pointer_after: meta.read_pointer,
+            record: None,
+        })?;
+        self.audit(Action::Next, Some(record.meta.get_id()), "");
+
+        self.after_write()
+    }
+
+    pub fn get_all(&mut self) -> Result<Vec<Record>, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        Ok(self.backend.get_all()?)
+    }
+
+    /// Records, inactive ones included, for which `predicate` holds, in order.
+    pub fn find(&mut self, mut predicate: impl FnMut(&Record) -> bool) -> Result<Vec<Record>, StorageError> {
+        let mut records = self.get_all()?;
+        records.retain(|record| predicate(record));
+
+        Ok(records)
+    }
+
+    pub fn search(&mut self, filter: &SearchFilter) -> Result<Vec<Record>, StorageError> {
+        self.find(|record| filter.matches(record))
+    }
+
+    /// Writes every record, inactive ones included, to `out`. Returns how many
+    /// records there were.
+    pub fn export(&mut self, out: &mut impl Write, format: ExportFormat) -> Result<u64, StorageError> {
+        let records = self.get_all()?;
+        let current_id = match self.current_record() {
+            Ok(record) => Some(record.meta.get_id()),
+            Err(StorageError::Empty) => None,
+            Err(e) => return Err(e),
+        };
+
+        export::write_records(out, &records, current_id, format)?;
+
+        Ok(records.len() as u64)
+    }
+
+    /// Saves `values` in order. With `dedupe`, values already among the active
+    /// records, or earlier in `values`, are skipped. The batch is appended
+    /// under one lock and committed with a single meta update: an error
+    /// before that leaves none of it saved, one after is `AfterImport`.
+    pub fn import(&mut self, values: Vec<String>, dedupe: bool) -> Result<ImportReport, StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut meta = self.backend.get_meta()?;
+        let mut report = ImportReport::default();
+
+        let mut seen = HashSet::new();
+        if dedupe {
+            let mut pointer = 0;
+            while pointer < meta.write_pointer {
+                let record = self.backend.pick(pointer)?;
+                pointer += record.size();
+                if record.meta.is_active() {
+                    seen.insert(record.data);
+                }
+            }
         }
 
-        self.meta_store.update(meta)?;
+        let values: Vec<String> = values
+            .into_iter()
+            .filter(|value| {
+                let is_new = !dedupe || seen.insert(value.clone());
+                report.skipped += !is_new as u64;
+                is_new
+            })
+            .collect();
+
+        if values.is_empty() {
+            return Ok(report);
+        }
+
+        if let Some(max_records) = self.max_records {
+            let active_records = match meta.active_records {
+                Some(active_records) => active_records,
+                None => self.count_active(&meta)?,
+            };
+            if active_records + values.len() as u64 > max_records {
+                return Err(StorageError::QuotaExceeded(max_records));
+            }
+            meta.active_records = Some(active_records);
+        }
+
+        // Nothing counts as saved until the meta points past it
+        let data_end = self.backend.data_len()?;
+        let mut added = Vec::with_capacity(values.len());
+        for value in values {
+            let record = Record::new(value, meta.total_records_added + 1);
+            let size = match self.backend.push(&record) {
+                Ok(size) => size,
+                Err(e) => {
+                    let _ = self.backend.truncate(data_end);
+                    return Err(e.into());
+                }
+            };
+            meta.total_records_added += 1;
+            added.push((meta.write_pointer, record));
+            meta.write_pointer += size;
+        }
+
+        meta.active_records = meta.active_records.map(|n| n + added.len() as u64);
+        if let Err(e) = self.backend.update_meta(meta) {
+            let _ = self.backend.truncate(data_end);
+            return Err(e.into());
+        }
+
+        report.added = added.len() as u64;
+        let logged = added.iter().try_for_each(|(pointer, record)| {
+            let id = record.meta.get_id();
+            self.journal(JournalEntry {
+                operation: Operation::Save,
+                read_pointer_before: meta.read_pointer,
+                read_pointer_after: meta.read_pointer,
+                record: Some((*pointer, id)),
+            })?;
+            self.audit(Action::Save, Some(id), "");
+            self.update_index(index::add_line(*pointer, &record.data))
+        });
+
+        self.notifier.notify();
+        logged
+            .and_then(|()| self.flush())
+            .map_err(|error| StorageError::AfterImport { added: report.added, error: Box::new(error) })?;
+
+        Ok(report)
+    }
+
+    /// Checks every record and the meta against each other without changing
+    /// anything.
+    pub fn verify(&mut self) -> Result<VerifyReport, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        let meta = self.backend.get_meta()?;
+        Ok(Scan::run(self.backend.as_mut(), &meta)?.report)
+    }
+
+    /// Like `verify`, then fixes what it found: everything from the first
+    /// unreadable record on is cut off and the meta is brought in line with
+    /// the records that are left. Returns the issues found before repairing.
+    pub fn repair(&mut self) -> Result<VerifyReport, StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let meta = self.backend.get_meta()?;
+        let scan = Scan::run(self.backend.as_mut(), &meta)?;
+
+        if scan.report.issues.iter().any(|issue| issue.is_repairable()) {
+            self.backend.truncate(scan.data_end)?;
+            self.backend.update_meta(scan.repaired_meta(meta))?;
+            self.clear_journal()?;
+            if self.has_index()? {
+                self.write_index()?;
+            }
+            self.flush()?;
+        }
+
+        Ok(scan.report)
+    }
+
+    /// Copies every active record that can still be read into `destination`,
+    /// ids included, skipping over damaged parts of the data.
+    pub fn salvage_into(&mut self, destination: &mut Storage) -> Result<SalvageReport, StorageError> {
+        let report = {
+            let _lock = self.lock(LockKind::Shared)?;
+
+            integrity::salvage(self.backend.as_mut(), |record| destination.save_with_id(record.data, record.meta.get_id()))?
+        };
+        destination.flush()?;
+
+        Ok(report)
+    }
+
+    /// Records the current state under `name`, to go back to or look at later.
+    pub fn create_snapshot(&mut self, name: &str) -> Result<(), StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut snapshots = self.read_snapshots()?;
+        if snapshots.iter().any(|s| s.name == name) {
+            return Err(StorageError::SnapshotAlreadyExists(name.to_string()));
+        }
+
+        let meta = self.backend.get_meta()?;
+        let mut inactive = Vec::new();
+        let mut pointer = 0;
+        while pointer < meta.write_pointer {
+            let record = self.backend.pick(pointer)?;
+            if !record.meta.is_active() {
+                inactive.push(pointer);
+            }
+            pointer += record.size();
+        }
+
+        snapshots.push(Snapshot { name: name.to_string(), created_at: Record::now(), meta, inactive });
+        self.write_snapshots(&snapshots)
+    }
+
+    pub fn get_snapshots(&mut self) -> Result<Vec<Snapshot>, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        self.read_snapshots()
+    }
+
+    pub fn delete_snapshot(&mut self, name: &str) -> Result<(), StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut snapshots = self.read_snapshots()?;
+        let index = snapshots
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| StorageError::SnapshotNotFound(name.to_string()))?;
+
+        snapshots.remove(index);
+        self.write_snapshots(&snapshots)
+    }
+
+    /// Goes back to a snapshot: records saved since are dropped, removed ones
+    /// come back and the cursor returns to where it was. Snapshots taken
+    /// after it point at dropped data, so they're deleted; their names are
+    /// returned.
+    pub fn restore_snapshot(&mut self, name: &str) -> Result<Vec<String>, StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut snapshots = self.read_snapshots()?;
+        let snapshot = snapshots
+            .iter()
+            .find(|s| s.name == name)
+            .cloned()
+            .ok_or_else(|| StorageError::SnapshotNotFound(name.to_string()))?;
+
+        if snapshot.meta.write_pointer > self.backend.data_len()? {
+            return Err(StorageError::SnapshotOutdated(name.to_string()));
+        }
+
+        // Check the whole prefix before changing anything
+        let mut records = Vec::new();
+        let mut pointer = 0;
+        while pointer < snapshot.meta.write_pointer {
+            let record = self.backend.pick(pointer)?;
+            let size = record.size();
+            records.push((pointer, record));
+            pointer += size;
+        }
+
+        if pointer != snapshot.meta.write_pointer {
+            return Err(StorageError::SnapshotOutdated(name.to_string()));
+        }
+
+        let mut active_records = 0;
+        for (pointer, record) in records {
+            let is_active = !snapshot.inactive.contains(&pointer);
+            active_records += is_active as u64;
+            if record.meta.is_active() != is_active {
+                self.backend.set_active(pointer, is_active)?;
+            }
+        }
+
+        // Ids handed out since aren't handed out again
+        let total_records_added = self.backend.get_meta()?.total_records_added;
+
+        self.backend.truncate(snapshot.meta.write_pointer)?;
+        self.backend.update_meta(Meta {
+            total_records_added: total_records_added.max(snapshot.meta.total_records_added),
+            active_records: Some(active_records),
+            ..snapshot.meta
+        })?;
+
+        let (kept, dropped): (Vec<Snapshot>, Vec<Snapshot>) = snapshots
+            .drain(..)
+            .partition(|s| s.name == name || s.meta.write_pointer <= snapshot.meta.write_pointer);
+        self.write_snapshots(&kept)?;
+        self.clear_journal()?;
+        if self.has_index()? {
+            self.write_index()?;
+        }
+        self.flush()?;
+
+        Ok(dropped.into_iter().map(|s| s.name).collect())
+    }
+
+    /// The storage as it was at a snapshot, as a read-only copy in memory.
+    pub fn open_snapshot(&mut self, name: &str) -> Result<Storage, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        let snapshot = self
+            .read_snapshots()?
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| StorageError::SnapshotNotFound(name.to_string()))?;
+
+        if snapshot.meta.write_pointer > self.backend.data_len()? {
+            return Err(StorageError::SnapshotOutdated(name.to_string()));
+        }
+
+        let mut view = MemoryBackend::from_parts(
+            snapshot.meta,
+            self.backend.read_at(0, snapshot.meta.write_pointer as usize)?,
+        );
+
+        let mut pointer = 0;
+        while pointer < snapshot.meta.write_pointer {
+            let record = view
+                .pick(pointer)
+                .map_err(|_| StorageError::SnapshotOutdated(name.to_string()))?;
+            view.set_active(pointer, !snapshot.inactive.contains(&pointer))?;
+            pointer += record.size();
+        }
+
+        let mut storage = Storage::with_backend(Box::new(view));
+        storage.read_only = true;
+
+        Ok(storage)
+    }
+
+    fn read_snapshots(&mut self) -> Result<Vec<Snapshot>, StorageError> {
+        match self.backend.read_sidecar(snapshot::SIDECAR)? {
+            Some(content) => {
+                let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+                let content = String::from_utf8(content).map_err(|e| invalid(e.to_string()))?;
+
+                Ok(snapshot::parse(&content).map_err(|e| invalid(format!("snapshots: {e}")))?)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn write_snapshots(&mut self, snapshots: &[Snapshot]) -> Result<(), StorageError> {
+        Ok(self.backend.write_sidecar(snapshot::SIDECAR, snapshot::serialize(snapshots).as_bytes())?)
+    }
+
+    /// Reverts the most recent save, removal or move to the next record that
+    /// wasn't undone yet, and returns it. Saved records are deactivated
+    /// rather than dropped, so their ids aren't handed out again.
+    pub fn undo(&mut self) -> Result<JournalEntry, StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut journal = self.read_journal()?;
+        let entry = journal.undo().ok_or(StorageError::NothingToUndo)?;
+
+        let is_active = match entry.operation {
+            Operation::Save => Some(false),
+            Operation::Remove => Some(true),
+            Operation::MoveNext => None,
+        };
+        self.replay(entry, is_active, entry.read_pointer_before)?;
+        self.backend.append_sidecar(journal::SIDECAR, journal::UNDO_LINE.as_bytes())?;
+        self.audit(Action::Undo, entry.record.map(|(_, id)| id), &entry.operation.to_string());
+
+        self.notifier.notify();
+        self.after_write()?;
+
+        Ok(entry)
+    }
+
+    /// Does an undone operation again and returns it. Any new operation since
+    /// the undo makes it impossible.
+    pub fn redo(&mut self) -> Result<JournalEntry, StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut journal = self.read_journal()?;
+        let entry = journal.redo().ok_or(StorageError::NothingToRedo)?;
+
+        let is_active = match entry.operation {
+            Operation::Save => Some(true),
+            Operation::Remove => Some(false),
+            Operation::MoveNext => None,
+        };
+        self.replay(entry, is_active, entry.read_pointer_after)?;
+        self.backend.append_sidecar(journal::SIDECAR, journal::REDO_LINE.as_bytes())?;
+        self.audit(Action::Redo, entry.record.map(|(_, id)| id), &entry.operation.to_string());
+
+        self.notifier.notify();
+        self.after_write()?;
+
+        Ok(entry)
+    }
+
+    /// Sets the flag of the entry's record, if it has one, and the cursor.
+    fn replay(&mut self, entry: JournalEntry, is_active: Option<bool>, read_pointer: u64) -> Result<(), StorageError> {
+        let mut meta = self.backend.get_meta()?;
+
+        if let (Some((pointer, id)), Some(is_active)) = (entry.record, is_active) {
+            let record = match self.backend.pick(pointer) {
+                Ok(record) if pointer < meta.write_pointer && record.meta.get_id() == id => record,
+                _ => return Err(StorageError::JournalOutdated),
+            };
+
+            if record.meta.is_active() != is_active {
+                self.backend.set_active(pointer, is_active)?;
+                meta.active_records =
+                    meta.active_records.map(|n| if is_active { n + 1 } else { n.saturating_sub(1) });
+            }
+            self.update_index(if is_active {
+                index::add_line(pointer, &record.data)
+            } else {
+                index::remove_line(pointer)
+            })?;
+        }
+
+        if read_pointer > meta.write_pointer {
+            return Err(StorageError::JournalOutdated);
+        }
+        meta.read_pointer = read_pointer;
+
+        Ok(self.backend.update_meta(meta)?)
+    }
+
+    fn journal(&mut self, entry: JournalEntry) -> Result<(), StorageError> {
+        self.backend.append_sidecar(journal::SIDECAR, entry.to_line().as_bytes())?;
+
+        // Keeps the log from growing past what can be undone
+        self.journal_appends += 1;
+        if self.journal_appends.is_multiple_of(journal::LIMIT) {
+            let log = self.read_journal()?.to_log();
+            self.backend.write_sidecar(journal::SIDECAR, log.as_bytes())?;
+        }
 
         Ok(())
     }
 
-    pub fn get_all(&mut self) -> Result<Vec<Record>, StorageError> {
-        Ok(self.data_store.get_all()?)
+    /// The operation already happened by the time it's logged, so failing
+    /// to log it doesn't fail the operation.
+    fn audit(&self, action: Action, record_id: Option<u64>, detail: &str) {
+        if let Some((audit_log, storage_name)) = &self.audit_log {
+            let _ = audit_log.record(action, storage_name, record_id, detail);
+        }
+    }
+
+    fn read_journal(&mut self) -> Result<Journal, StorageError> {
+        let content = self.backend.read_sidecar(journal::SIDECAR)?.unwrap_or_default();
+        Ok(Journal::parse(&String::from_utf8_lossy(&content)))
+    }
+
+    /// Forgets every operation, for changes that leave the journal pointing
+    /// at data that's gone.
+    fn clear_journal(&mut self) -> Result<(), StorageError> {
+        Ok(self.backend.write_sidecar(journal::SIDECAR, b"")?)
+    }
+
+    /// Indexes the words of every active record, replacing any index built
+    /// before. Saves and removals keep it up to date from then on. Returns
+    /// how many records it holds.
+    pub fn build_index(&mut self) -> Result<u64, StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        self.write_index()
+    }
+
+    /// Stops keeping a full-text index.
+    pub fn drop_index(&mut self) -> Result<(), StorageError> {
+        let _lock = self.lock_for_write()?;
+
+        self.index = None;
+        Ok(self.backend.write_sidecar(index::SIDECAR, b"")?)
+    }
+
+    /// Active records holding every word of `query`, looked up in the index
+    /// rather than read one by one. With `prefix`, query words also match
+    /// longer words they start.
+    pub fn search_index(&mut self, query: &str, prefix: bool) -> Result<Vec<Record>, StorageError> {
+        let _lock = self.lock(LockKind::Shared)?;
+
+        self.refresh_index()?;
+        let pointers = self.index.as_ref().ok_or(StorageError::NoIndex)?.search(query, prefix);
+
+        let mut records = Vec::with_capacity(pointers.len());
+        for pointer in pointers {
+            let record = self.backend.pick(pointer)?;
+            if record.meta.is_active() {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// A dropped index is left behind empty.
+    fn has_index(&mut self) -> Result<bool, StorageError> {
+        Ok(self.backend.sidecar_len(index::SIDECAR)?.is_some_and(|len| len > 0))
+    }
+
+    fn write_index(&mut self) -> Result<u64, StorageError> {
+        let meta = self.backend.get_meta()?;
+        let mut content = index::header();
+        let mut count = 0;
+
+        let mut pointer = 0;
+        while pointer < meta.write_pointer {
+            let record = self.backend.pick(pointer)?;
+            if record.meta.is_active() {
+                content.push_str(&index::add_line(pointer, &record.data));
+                count += 1;
+            }
+            pointer += record.size();
+        }
+
+        self.backend.write_sidecar(index::SIDECAR, content.as_bytes())?;
+        self.index = Index::parse(content.as_bytes());
+
+        Ok(count)
+    }
+
+    /// Catches up with what other handles appended to the index since it was
+    /// last read, or reads it again if it was rebuilt.
+    fn refresh_index(&mut self) -> Result<(), StorageError> {
+        let len = match self.backend.sidecar_len(index::SIDECAR)? {
+            Some(len) if len > 0 => len,
+            _ => {
+                self.index = None;
+                return Ok(());
+            }
+        };
+
+        if let Some(mut cached) = self.index.take() {
+            let header = self.backend.read_sidecar_range(index::SIDECAR, 0, cached.header().len())?;
+            if header == cached.header().as_bytes() && cached.consumed() <= len {
+                let appended = self.backend.read_sidecar_range(
+                    index::SIDECAR,
+                    cached.consumed(),
+                    (len - cached.consumed()) as usize,
+                )?;
+                cached.catch_up(&appended);
+                self.index = Some(cached);
+
+                return Ok(());
+            }
+        }
+
+        let content = self.backend.read_sidecar(index::SIDECAR)?.unwrap_or_default();
+        let index = Index::parse(&content).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "index: missing header, build it again")
+        })?;
+        self.index = Some(index);
+
+        Ok(())
+    }
+
+    /// Appends a change to the index, if there is one, and compacts the log
+    /// once most of it is dead.
+    fn update_index(&mut self, line: String) -> Result<(), StorageError> {
+        let Some(len) = self.backend.sidecar_len(index::SIDECAR)?.filter(|&len| len > 0) else {
+            return Ok(());
+        };
+
+        self.backend.append_sidecar(index::SIDECAR, line.as_bytes())?;
+
+        // Skip the read when nothing else changed it
+        match self.index.as_mut() {
+            Some(cached) if cached.consumed() == len => cached.catch_up(line.as_bytes()),
+            _ => self.refresh_index()?,
+        }
+
+        // The log only grows, so it's rewritten once most of it is dead
+        if let Some(cached) = &self.index
+            && cached.needs_compaction()
+        {
+            let log = cached.to_log();
+            self.backend.write_sidecar(index::SIDECAR, log.as_bytes())?;
+            self.index = Index::parse(log.as_bytes());
+        }
+
+        Ok(())
+    }
+
+    /// Forces all pending writes to disk regardless of the durability policy.
+    pub fn flush(&mut self) -> Result<(), StorageError> {
+        self.backend.sync()?;
+        self.sync_tracker.mark_synced();
+
+        Ok(())
     }
 
     pub fn file_path(dir_path: &str, file_path: &str) -> std::path::PathBuf {
         let base = Path::new(dir_path);
         base.join(file_path)
     }
+
+    /// First active record at or after `from`, wrapping around to the start.
+    fn next_active(&mut self, meta: &Meta, from: u64) -> std::io::Result<Option<(u64, Record)>> {
+        let from = from.min(meta.write_pointer);
+
+        for (start, end) in [(from, meta.write_pointer), (0, from)] {
+            let mut pointer = start;
+            while pointer < end {
+                let record = self.backend.pick(pointer)?;
+                if record.meta.is_active() {
+                    return Ok(Some((pointer, record)));
+                }
+                pointer += record.size();
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn count_active(&mut self, meta: &Meta) -> std::io::Result<u64> {
+        let mut count = 0;
+        let mut pointer = 0;
+        while pointer < meta.write_pointer {
+            let record = self.backend.pick(pointer)?;
+            count += record.meta.is_active() as u64;
+            pointer += record.size();
+        }
+
+        Ok(count)
+    }
+
+    fn find_active_by_id(&mut self, meta: &Meta, id: u64) -> std::io::Result<Option<(u64, Record)>> {
+        let mut pointer = 0;
+        while pointer < meta.write_pointer {
+            let record = self.backend.pick(pointer)?;
+            if record.meta.is_active() && record.meta.get_id() == id {
+                return Ok(Some((pointer, record)));
+            }
+            pointer += record.size();
+        }
+
+        Ok(None)
+    }
+
+    pub(crate) fn get_notifier(&self) -> Arc<Notifier> {
+        self.notifier.clone()
+    }
+
+    /// Operations lock the storage for their whole duration, so several
+    /// processes can share it without interleaving their writes.
+    fn lock(&mut self, kind: LockKind) -> Result<BackendLock, StorageError> {
+        self.backend
+            .lock(kind, FileLock::DEFAULT_TIMEOUT)
+            .map_err(|e| if FileLock::is_contention(&e) { StorageError::Locked } else { e.into() })
+    }
+
+    fn lock_for_write(&mut self) -> Result<BackendLock, StorageError> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly);
+        }
+
+        self.lock(LockKind::Exclusive)
+    }
+
+    fn after_write(&mut self) -> Result<(), StorageError> {
+        if self.sync_tracker.record_op() {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        if self.sync_tracker.has_pending() {
+            let _ = self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrity::VerifyIssue;
+
+    #[test]
+    fn pick_on_empty_storage_fails() {
+        let mut storage = Storage::in_memory();
+
+        assert!(matches!(storage.pick(), Err(StorageError::Empty)));
+    }
+
+    #[test]
+    fn move_next_wraps_around() {
+        let mut storage = Storage::in_memory();
+        storage.save("first".to_string()).unwrap();
+        storage.save("second".to_string()).unwrap();
+
+        assert_eq!(storage.pick().unwrap(), "first");
+        storage.move_next().unwrap();
+        assert_eq!(storage.pick().unwrap(), "second");
+        storage.move_next().unwrap();
+        assert_eq!(storage.pick().unwrap(), "first");
+    }
+
+    #[test]
+    fn get_all_returns_records_in_order() {
+        let mut storage = Storage::in_memory();
+        storage.save("first".to_string()).unwrap();
+        storage.save("second".to_string()).unwrap();
+
+        let records = storage.get_all().unwrap();
+        let ids: Vec<u64> = records.iter().map(|r| r.meta.get_id()).collect();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[test]
+    fn removed_records_are_skipped() {
+        let mut storage = Storage::in_memory();
+        for value in ["first", "second", "third"] {
+            storage.save(value.to_string()).unwrap();
+        }
+
+        storage.remove(1).unwrap();
+        assert_eq!(storage.pick().unwrap(), "second");
+
+        storage.remove(3).unwrap();
+        storage.move_next().unwrap();
+        assert_eq!(storage.pick().unwrap(), "second");
+
+        storage.remove(2).unwrap();
+        assert!(matches!(storage.pick(), Err(StorageError::Empty)));
+        assert!(matches!(storage.remove(2), Err(StorageError::RecordNotFound(2))));
+
+        storage.save("fourth".to_string()).unwrap();
+        assert_eq!(storage.pick().unwrap(), "fourth");
+    }
+
+    #[test]
+    fn pick_refuses_sizes_past_the_data() {
+        let dir = std::env::temp_dir().join(format!("re-queue-pick-size-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // Claims nearly 2^64 bytes of payload, which must not be allocated
+        let mut record = Record::new("first".to_string(), 1);
+        record.meta = RecordHeader::new(u64::MAX - 10, 1, 0, 0);
+
+        let file_backend = FileBackend::open(&dir.join("test.mt"), &dir.join("test.dt")).unwrap();
+        for mut backend in [Box::new(file_backend) as Box<dyn StorageBackend>, Box::new(MemoryBackend::new())] {
+            backend.push(&record).unwrap();
+            assert!(matches!(backend.pick(0), Err(e) if e.kind() == std::io::ErrorKind::InvalidData));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_backend_clones_share_a_lock() {
+        let mut backend = MemoryBackend::new();
+        let mut clone = backend.clone();
+        let is_locked = |result: std::io::Result<BackendLock>| {
+            matches!(result, Err(e) if FileLock::is_contention(&e))
+        };
+
+        let shared = backend.lock(LockKind::Shared, Duration::ZERO).unwrap();
+        assert!(clone.lock(LockKind::Shared, Duration::ZERO).is_ok());
+        assert!(is_locked(clone.lock(LockKind::Exclusive, Duration::ZERO)));
+        assert!(backend.duplicate().lock(LockKind::Exclusive, Duration::ZERO).is_ok());
+
+        drop(shared);
+        let _exclusive = clone.lock(LockKind::Exclusive, Duration::ZERO).unwrap();
+        assert!(is_locked(backend.lock(LockKind::Shared, Duration::from_millis(10))));
+    }
+
+    #[test]
+    fn repair_cuts_off_torn_record() {
+        let mut backend = MemoryBackend::new();
+        let mut storage = Storage::with_backend(Box::new(backend.clone()));
+        for value in ["first", "second", "third"] {
+            storage.save(value.to_string()).unwrap();
+        }
+        storage.move_next().unwrap();
+        storage.move_next().unwrap();
+        assert!(storage.verify().unwrap().is_ok());
+
+        let data_len = backend.data_len().unwrap();
+        backend.truncate(data_len - 2).unwrap();
+
+        let report = storage.verify().unwrap();
+        assert_eq!(report.records, 2);
+        assert!(matches!(report.issues[0], VerifyIssue::TruncatedRecord { .. }));
+        assert!(report.issues.contains(&VerifyIssue::WritePointerPastData { write_pointer: 163, data_end: 109 }));
+
+        storage.repair().unwrap();
+        assert!(storage.verify().unwrap().is_ok());
+        assert_eq!(storage.get_all().unwrap().len(), 2);
+
+        storage.save("fourth".to_string()).unwrap();
+        assert_eq!(storage.pick_record().unwrap().meta.get_id(), 4);
+    }
+
+    #[test]
+    fn repair_keeps_records_past_write_pointer() {
+        let mut backend = MemoryBackend::new();
+        let mut storage = Storage::with_backend(Box::new(backend.clone()));
+        storage.save("first".to_string()).unwrap();
+        storage.save("second".to_string()).unwrap();
+
+        // A crash between appending the second record and updating the meta
+        let meta = backend.get_meta().unwrap();
+        backend.update_meta(Meta { write_pointer: 54, total_records_added: 1, ..meta }).unwrap();
+
+        let report = storage.repair().unwrap();
+        assert!(matches!(report.issues[0], VerifyIssue::UnreferencedRecords { .. }));
+        assert!(storage.verify().unwrap().is_ok());
+        assert_eq!(storage.get_all().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn snapshot_undoes_saves_and_removals() {
+        let mut storage = Storage::in_memory();
+        for value in ["first", "second"] {
+            storage.save(value.to_string()).unwrap();
+        }
+        storage.remove(1).unwrap();
+        storage.create_snapshot("before").unwrap();
+
+        storage.save("third".to_string()).unwrap();
+        storage.remove(2).unwrap();
+        storage.create_snapshot("after").unwrap();
+
+        let mut view = storage.open_snapshot("before").unwrap();
+        assert_eq!(view.pick().unwrap(), "second");
+        assert!(matches!(view.save("x".to_string()), Err(StorageError::ReadOnly)));
+
+        assert_eq!(storage.restore_snapshot("before").unwrap(), ["after"]);
+        assert_eq!(storage.pick().unwrap(), "second");
+        assert_eq!(storage.get_all().unwrap().len(), 2);
+        assert!(storage.verify().unwrap().is_ok());
+
+        storage.save("fourth".to_string()).unwrap();
+        assert_eq!(storage.get_by_id(4).unwrap().data, "fourth");
+    }
+
+    #[test]
+    fn index_follows_changes_from_every_handle() {
+        let backend = MemoryBackend::new();
+        let mut storage = Storage::with_backend(Box::new(backend.clone()));
+        let mut other = Storage::with_backend(Box::new(backend));
+
+        storage.save("Buy milk".to_string()).unwrap();
+        assert!(matches!(storage.search_index("milk", false), Err(StorageError::NoIndex)));
+        assert_eq!(storage.build_index().unwrap(), 1);
+
+        other.save("milkshake for Bob".to_string()).unwrap();
+        storage.save("call bob".to_string()).unwrap();
+        let ids = |records: Vec<Record>| records.iter().map(|r| r.meta.get_id()).collect::<Vec<_>>();
+        assert_eq!(ids(storage.search_index("milk", true).unwrap()), [1, 2]);
+        assert_eq!(ids(storage.search_index("BOB", false).unwrap()), [2, 3]);
+
+        other.remove(2).unwrap();
+        assert_eq!(ids(storage.search_index("bob", false).unwrap()), [3]);
+        storage.undo().unwrap();
+        assert_eq!(ids(other.search_index("bob", false).unwrap()), [2, 3]);
+
+        other.build_index().unwrap();
+        assert_eq!(ids(storage.search_index("milk", false).unwrap()), [1]);
+        other.drop_index().unwrap();
+        assert!(matches!(storage.search_index("milk", false), Err(StorageError::NoIndex)));
+    }
+
+    #[test]
+    fn index_log_is_compacted() {
+        let mut backend = MemoryBackend::new();
+        let mut storage = Storage::with_backend(Box::new(backend.clone()));
+        storage.save("kept".to_string()).unwrap();
+        storage.build_index().unwrap();
+
+        // Each round from a new handle, the way separate runs of the app go
+        for id in 2..=100 {
+            let mut handle = Storage::with_backend(Box::new(backend.clone()));
+            handle.save(format!("gone {id}")).unwrap();
+            handle.remove(id).unwrap();
+        }
+
+        let log_len = backend.sidecar_len(index::SIDECAR).unwrap().unwrap();
+        assert!(log_len < 200, "index log is {log_len} bytes");
+        assert_eq!(storage.search_index("kept", false).unwrap().len(), 1);
+        assert!(storage.search_index("gone", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn undo_and_redo_revert_operations() {
+        let mut storage = Storage::in_memory();
+        for value in ["first", "second", "third"] {
+            storage.save(value.to_string()).unwrap();
+        }
+        storage.move_next().unwrap();
+        storage.remove(2).unwrap();
+        assert_eq!(storage.pick().unwrap(), "third");
+
+        assert_eq!(storage.undo().unwrap().operation, Operation::Remove);
+        assert_eq!(storage.pick().unwrap(), "second");
+        assert_eq!(storage.undo().unwrap().operation, Operation::MoveNext);
+        assert_eq!(storage.pick().unwrap(), "first");
+        assert_eq!(storage.undo().unwrap().record, Some((109, 3)));
+        assert!(matches!(storage.get_by_id(3), Err(StorageError::RecordNotFound(3))));
+
+        assert_eq!(storage.redo().unwrap().operation, Operation::Save);
+        assert_eq!(storage.get_by_id(3).unwrap().data, "third");
+
+        storage.save("fourth".to_string()).unwrap();
+        assert!(matches!(storage.redo(), Err(StorageError::NothingToRedo)));
+        assert_eq!(storage.get_by_id(4).unwrap().data, "fourth");
+    }
+
+    #[test]
+    fn import_saves_the_whole_batch_or_none_of_it() {
+        let mut storage = Storage::in_memory();
+        storage.save("kept".to_string()).unwrap();
+        let values = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+
+        let report = storage.import(values(&["kept", "a", "a", "b"]), true).unwrap();
+        assert_eq!(report, ImportReport { added: 2, skipped: 2 });
+        assert_eq!(storage.get_all().unwrap().iter().map(|r| r.meta.get_id()).collect::<Vec<_>>(), [1, 2, 3]);
+
+        storage.set_max_records(Some(4));
+        assert!(matches!(storage.import(values(&["c", "d"]), false), Err(StorageError::QuotaExceeded(4))));
+        assert_eq!(storage.get_all().unwrap().len(), 3);
+
+        storage.undo().unwrap();
+        assert_eq!(storage.get_all().unwrap().iter().filter(|r| r.meta.is_active()).count(), 2);
+    }
+
+    #[test]
+    fn save_respects_max_records() {
+        let mut storage = Storage::in_memory();
+        storage.set_max_records(Some(1));
+        storage.save("first".to_string()).unwrap();
+
+        assert!(matches!(storage.save("second".to_string()), Err(StorageError::QuotaExceeded(1))));
+
+        storage.remove(1).unwrap();
+        assert!(storage.save("second".to_string()).is_ok());
+    }
+
+    #[test]
+    fn meta_keeps_the_active_count() {
+        let mut backend = MemoryBackend::new();
+        let mut storage = Storage::with_backend(Box::new(backend.clone()));
+        for value in ["first", "second", "third"] {
+            storage.save(value.to_string()).unwrap();
+        }
+        storage.remove(2).unwrap();
+        storage.undo().unwrap();
+        storage.remove(3).unwrap();
+        assert_eq!(backend.get_meta().unwrap().active_records, Some(2));
+
+        // Metas written before the count was kept get it on the next save under a quota
+        let meta = backend.get_meta().unwrap();
+        assert_eq!(Meta::from_bytes(&meta.to_bytes()[..Meta::LEGACY_SIZE]).active_records, None);
+        backend.update_meta(Meta { active_records: None, ..meta }).unwrap();
+        storage.set_max_records(Some(2));
+        assert!(matches!(storage.save("fourth".to_string()), Err(StorageError::QuotaExceeded(2))));
+        assert_eq!(backend.get_meta().unwrap().active_records, None);
+
+        backend.update_meta(Meta { active_records: Some(7), ..meta }).unwrap();
+        let report = storage.repair().unwrap();
+        assert_eq!(report.issues, [VerifyIssue::ActiveRecordsMiscounted { active_records: 7, counted: 2 }]);
+        assert_eq!(backend.get_meta().unwrap().active_records, Some(2));
+    }
 }
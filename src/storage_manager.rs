@@ -1,68 +1,1402 @@
-use crate::{config::Config, config_store::ConfigStore, storage::Storage};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::{self, File},
+    io::{BufRead, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    audit::{Action, AuditEntry, AuditFilter, AuditLog},
+    backend::{FileBackend, StorageBackend},
+    backup::{self, ArchiveFile, BackupError},
+    config::{self, Config, ConfigError, InvalidStorageName, RotationEntry, StorageSettings},
+    config_store::{ConfigStore, ConfigStoreError},
+    export::ExportFormat,
+    file_lock::{FileLock, LockKind},
+    import::{self, ImportError, ImportReport},
+    integrity::SalvageReport,
+    memory_backend::MemoryBackend,
+    meta::Meta,
+    paths::StoragePaths,
+    record::Record,
+    snapshot,
+    storage::{Storage, StorageError},
+};
+
+#[derive(Debug)]
+pub enum StorageManagerError {
+    Io(std::io::Error),
+    Storage(StorageError),
+    Config(ConfigError),
+    ConfigStore(ConfigStoreError),
+    Import(ImportError),
+    Backup(BackupError),
+    NoActiveStorage,
+    SameStorage,
+    NoRotation,
+}
+
+impl From<std::io::Error> for StorageManagerError {
+    fn from(e: std::io::Error) -> Self {
+        StorageManagerError::Io(e)
+    }
+}
+
+impl From<StorageError> for StorageManagerError {
+    fn from(e: StorageError) -> Self {
+        StorageManagerError::Storage(e)
+    }
+}
+
+impl From<ConfigError> for StorageManagerError {
+    fn from(e: ConfigError) -> Self {
+        StorageManagerError::Config(e)
+    }
+}
+
+impl From<ConfigStoreError> for StorageManagerError {
+    fn from(e: ConfigStoreError) -> Self {
+        StorageManagerError::ConfigStore(e)
+    }
+}
+
+impl From<ImportError> for StorageManagerError {
+    fn from(e: ImportError) -> Self {
+        StorageManagerError::Import(e)
+    }
+}
+
+impl From<BackupError> for StorageManagerError {
+    fn from(e: BackupError) -> Self {
+        StorageManagerError::Backup(e)
+    }
+}
+
+impl fmt::Display for StorageManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageManagerError::Io(e) => write!(f, "i/o error: {e}"),
+            StorageManagerError::Storage(e) => write!(f, "{e}"),
+            StorageManagerError::Config(e) => write!(f, "{e}"),
+            StorageManagerError::ConfigStore(e) => write!(f, "{e}"),
+            StorageManagerError::Import(e) => write!(f, "{e}"),
+            StorageManagerError::Backup(e) => write!(f, "{e}"),
+            StorageManagerError::NoActiveStorage => write!(f, "no storage is open"),
+            StorageManagerError::SameStorage => write!(f, "source and destination are the same storage"),
+            StorageManagerError::NoRotation => write!(f, "no rotation is set"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeOptions {
+    /// Keep the source ids instead of numbering records after the
+    /// destination's own. Records whose id the destination already holds
+    /// get a new one.
+    pub keep_ids: bool,
+    /// Skip records whose payload is already in the destination.
+    pub dedupe: bool,
+    /// Delete the source storage once everything is merged.
+    pub delete_source: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeReport {
+    pub added: u64,
+    pub skipped: u64,
+    /// Records added under a new id because theirs was taken.
+    pub reassigned: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    /// Storages that were registered but aren't in the archive. Their files
+    /// stay where they are, so `discover` can bring them back.
+    pub unregistered: Vec<String>,
+}
+
+/// Outcome of reconciling the config with the storages that actually exist.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DiscoveryReport {
+    /// Storages that existed without being in the config and were added to it.
+    pub registered: Vec<String>,
+    /// Storages in the config whose files are gone. They are kept, so the
+    /// user decides whether to delete them.
+    pub missing: Vec<String>,
+    /// Unregistered storages that have only one of their two files, left alone.
+    pub incomplete: Vec<String>,
+}
+
+/// Which of its files a storage found by `Backing::scan` has.
+#[derive(Debug, Clone, Copy, Default)]
+struct FoundFiles {
+    meta: bool,
+    data: bool,
+}
+
+impl FoundFiles {
+    fn is_complete(&self) -> bool {
+        self.meta && self.data
+    }
+}
+
+/// The files of a storage that go into a backup.
+struct StorageFiles {
+    meta: Vec<u8>,
+    data: Vec<u8>,
+    /// `None` if no snapshot was ever taken.
+    snapshots: Option<Vec<u8>>,
+}
+
+/// Where storages and the config live.
+enum Backing {
+    Files(StoragePaths),
+    /// Nothing touches the disk; storages are kept by name for the lifetime
+    /// of the manager.
+    Memory(HashMap<String, MemoryBackend>),
+}
+
+impl Backing {
+    fn open(&mut self, storage_name: &str) -> Result<Storage, StorageManagerError> {
+        match self {
+            Backing::Files(paths) => Ok(Storage::new(
+                &paths.storage_dir,
+                format!("{storage_name}.mt").as_str(),
+                format!("{storage_name}.dt").as_str(),
+            )?),
+            Backing::Memory(storages) => {
+                let backend = storages.entry(storage_name.to_string()).or_default();
+                Ok(Storage::with_backend(Box::new(backend.clone())))
+            }
+        }
+    }
+
+    fn remove(&mut self, storage_name: &str) -> Result<(), StorageManagerError> {
+        match self {
+            Backing::Files(paths) => {
+                let (meta_path, data_path) = Self::file_paths(paths, storage_name);
+                let _lock = Self::lock_files(&meta_path, LockKind::Exclusive)?;
+
+                Self::remove_sidecars(&meta_path)?;
+                for path in [data_path, meta_path] {
+                    match fs::remove_file(path) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                }
+            }
+            Backing::Memory(storages) => {
+                storages.remove(storage_name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rename(&mut self, storage_name: &str, new_storage_name: &str) -> Result<(), StorageManagerError> {
+        match self {
+            Backing::Files(paths) => {
+                let (meta_path, data_path) = Self::file_paths(paths, storage_name);
+                let (new_meta_path, new_data_path) = Self::file_paths(paths, new_storage_name);
+                Self::ensure_absent(&new_meta_path, &new_data_path)?;
+
+                let _lock = Self::lock_files(&meta_path, LockKind::Exclusive)?;
+
+                // Sidecars are optional, the data and meta files aren't. The meta
+                // goes last, as it holds the lock
+                let mut moves: Vec<(PathBuf, PathBuf, bool)> = Self::sidecar_paths(&meta_path)
+                    .zip(Self::sidecar_paths(&new_meta_path))
+                    .map(|(path, new_path)| (path, new_path, false))
+                    .collect();
+                moves.extend([(data_path, new_data_path, true), (meta_path, new_meta_path, true)]);
+
+                let mut moved = Vec::new();
+                for (path, new_path, is_required) in &moves {
+                    match fs::rename(path, new_path) {
+                        Ok(()) => moved.push((path, new_path)),
+                        Err(e) if e.kind() == ErrorKind::NotFound && !is_required => {}
+                        Err(e) => {
+                            // Put back what was moved, so the storage stays whole under its old name
+                            for (path, new_path) in moved.into_iter().rev() {
+                                let _ = fs::rename(new_path, path);
+                            }
+                            return Err(e.into());
+                        }
+                    }
+                }
+            }
+            Backing::Memory(storages) => {
+                if let Some(backend) = storages.remove(storage_name) {
+                    storages.insert(new_storage_name.to_string(), backend);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn copy(&mut self, storage_name: &str, new_storage_name: &str) -> Result<(), StorageManagerError> {
+        match self {
+            Backing::Files(paths) => {
+                let (meta_path, data_path) = Self::file_paths(paths, storage_name);
+                let (new_meta_path, new_data_path) = Self::file_paths(paths, new_storage_name);
+                Self::ensure_absent(&new_meta_path, &new_data_path)?;
+
+                // Writers take the same lock, so the pair is copied in a consistent state
+                let _lock = Self::lock_files(&meta_path, LockKind::Shared)?;
+                for (sidecar_path, new_sidecar_path) in Self::sidecar_paths(&meta_path).zip(Self::sidecar_paths(&new_meta_path)) {
+                    match fs::copy(sidecar_path, new_sidecar_path) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                }
+                fs::copy(data_path, new_data_path)?;
+                fs::copy(meta_path, new_meta_path)?;
+            }
+            Backing::Memory(storages) => {
+                let backend = storages.entry(storage_name.to_string()).or_default().duplicate();
+                storages.insert(new_storage_name.to_string(), backend);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every storage present, by name. Files whose names aren't valid storage
+    /// names were not made by us and are skipped.
+    fn scan(&self) -> Result<BTreeMap<String, FoundFiles>, StorageManagerError> {
+        let mut found = BTreeMap::<String, FoundFiles>::new();
+
+        match self {
+            Backing::Files(paths) => {
+                let entries = match fs::read_dir(&paths.storage_dir) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(found),
+                    Err(e) => return Err(e.into()),
+                };
+
+                for entry in entries {
+                    let path = entry?.path();
+                    let (Some(storage_name), Some(extension)) = (
+                        path.file_stem().and_then(|s| s.to_str()),
+                        path.extension().and_then(|s| s.to_str()),
+                    ) else {
+                        continue;
+                    };
+
+                    // Names from before the current rules still belong to
+                    // storages, so only leave out what can't be one at all.
+                    if storage_name.is_empty() {
+                        continue;
+                    }
+
+                    match extension {
+                        "mt" => found.entry(storage_name.to_string()).or_default().meta = true,
+                        "dt" => found.entry(storage_name.to_string()).or_default().data = true,
+                        _ => {}
+                    }
+                }
+            }
+            Backing::Memory(storages) => {
+                for storage_name in storages.keys() {
+                    found.insert(storage_name.clone(), FoundFiles { meta: true, data: true });
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Meta and data of a storage, byte for byte as they are in its files.
+    fn read_files(&mut self, storage_name: &str) -> Result<StorageFiles, StorageManagerError> {
+        match self {
+            Backing::Files(paths) => {
+                let (meta_path, data_path) = Self::file_paths(paths, storage_name);
+                let _lock = Self::lock_files(&meta_path, LockKind::Shared)?;
+
+                let snapshots = match fs::read(FileBackend::sidecar_path(&meta_path, snapshot::SIDECAR)) {
+                    Ok(content) => Some(content),
+                    Err(e) if e.kind() == ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                };
+
+                Ok(StorageFiles { meta: fs::read(meta_path)?, data: fs::read(data_path)?, snapshots })
+            }
+            Backing::Memory(storages) => {
+                let backend = storages.entry(storage_name.to_string()).or_default();
+                let snapshots = backend.read_sidecar(snapshot::SIDECAR)?;
+                let (meta, data) = backend.to_parts();
+                Ok(StorageFiles { meta: meta.to_bytes(), data, snapshots })
+            }
+        }
+    }
+
+    /// Replaces the files of a storage, creating them if needed. Each file
+    /// is written aside and renamed into place, so none is left half written.
+    /// The journal and index go, and so do the snapshots unless given.
+    fn write_files(
+        &mut self,
+        storage_name: &str,
+        meta: &[u8],
+        data: &[u8],
+        snapshots: Option<&[u8]>,
+    ) -> Result<(), StorageManagerError> {
+        match self {
+            Backing::Files(paths) => {
+                fs::create_dir_all(&paths.storage_dir)?;
+                let (meta_path, data_path) = Self::file_paths(paths, storage_name);
+                let _lock = Self::lock_files(&meta_path, LockKind::Exclusive)?;
+
+                // Sidecars describe the files being replaced
+                Self::remove_sidecars(&meta_path)?;
+                let snapshots_path = FileBackend::sidecar_path(&meta_path, snapshot::SIDECAR);
+                let files = [(data_path, Some(data)), (meta_path, Some(meta)), (snapshots_path, snapshots)];
+                for (path, content) in files.into_iter().filter_map(|(path, content)| Some((path, content?))) {
+                    let mut temp_path = path.clone().into_os_string();
+                    temp_path.push(".tmp");
+
+                    let mut file = File::create(&temp_path)?;
+                    file.write_all(content)?;
+                    file.sync_all()?;
+                    fs::rename(temp_path, path)?;
+                }
+            }
+            Backing::Memory(storages) => {
+                let mut backend = MemoryBackend::from_parts(Meta::from_bytes(meta), data.to_vec());
+                if let Some(snapshots) = snapshots {
+                    backend.write_sidecar(snapshot::SIDECAR, snapshots)?;
+                }
+                storages.insert(storage_name.to_string(), backend);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn file_paths(paths: &StoragePaths, storage_name: &str) -> (PathBuf, PathBuf) {
+        (
+            paths.storage_dir.join(format!("{storage_name}.mt")),
+            paths.storage_dir.join(format!("{storage_name}.dt")),
+        )
+    }
+
+    fn sidecar_paths(meta_path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+        FileBackend::SIDECARS.iter().map(|name| FileBackend::sidecar_path(meta_path, name))
+    }
+
+    fn remove_sidecars(meta_path: &Path) -> Result<(), StorageManagerError> {
+        for path in Self::sidecar_paths(meta_path) {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Locks a storage the same way `Storage` operations do. Returns `None`
+    /// when the storage has no files yet.
+    fn lock_files(meta_path: &Path, kind: LockKind) -> Result<Option<FileLock>, StorageManagerError> {
+        let file = match File::open(meta_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        FileLock::acquire(&file, kind, FileLock::DEFAULT_TIMEOUT)
+            .map(Some)
+            .map_err(|e| if FileLock::is_contention(&e) { StorageError::Locked.into() } else { e.into() })
+    }
+
+    /// Never overwrite files that the config doesn't know about.
+    fn ensure_absent(meta_path: &Path, data_path: &Path) -> Result<(), StorageManagerError> {
+        if meta_path.exists() || data_path.exists() {
+            return Err(ConfigError::StorageAlreadyExists.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Where round-robin is within the rotation. Kept for the session only.
+#[derive(Default)]
+struct RotationState {
+    position: usize,
+    taken: u32,
+}
 
 pub struct StorageManager {
     storage: Option<Storage>,
     config: Config,
+    backing: Backing,
+    rotation_state: RotationState,
+    audit_log: Option<AuditLog>,
 }
 
 impl StorageManager {
-    pub fn new() -> Self {
-        let config = ConfigStore::load(Self::CONFIG_PATH).unwrap();
-        let storage: Option<Storage> = if config.has_storages() {
-            Some(Self::create_or_open_storage(&config.get_active_storage().unwrap()))
-        } else {
-            None
+    const ARCHIVE_CONFIG_PATH: &str = "config";
+
+    pub fn new(paths: StoragePaths) -> Result<Self, StorageManagerError> {
+        if let Some(config_dir) = paths.config_path.parent() {
+            fs::create_dir_all(config_dir)?;
+        }
+
+        let config = ConfigStore::load(&paths.config_path)?;
+        let audit_log = AuditLog::open(paths.audit_log_path());
+        let mut backing = Backing::Files(paths);
+        let storage = match config.get_active_storage() {
+            Some(storage_name) => Some(Self::open_storage(&mut backing, &config, Some(&audit_log), &storage_name)?),
+            None => None,
         };
 
-        Self {
+        Ok(Self {
             storage,
             config,
+            backing,
+            rotation_state: RotationState::default(),
+            audit_log: Some(audit_log),
+        })
+    }
+
+    /// Manager that keeps config and storages in memory, for hermetic tests.
+    pub fn in_memory() -> Self {
+        Self {
+            storage: None,
+            config: Config::new(),
+            backing: Backing::Memory(HashMap::new()),
+            rotation_state: RotationState::default(),
+            audit_log: None,
+        }
+    }
+
+    /// Registers a new storage. It still has to be opened to become active.
+    pub fn create(&mut self, storage_name: &str) -> Result<(), StorageManagerError> {
+        config::validate_storage_name(storage_name).map_err(ConfigError::from)?;
+        if self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageAlreadyExists.into());
+        }
+
+        self.backing.open(storage_name)?;
+        self.update_config(|config| Ok(config.add_storage(storage_name)?))?;
+
+        self.audit(Action::Create, storage_name, None, "");
+
+        Ok(())
+    }
+
+    /// Makes a storage created earlier the active one.
+    pub fn open(&mut self, storage_name: &str) -> Result<(), StorageManagerError> {
+        if !self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+
+        // Opening the storage is up to `update_config`, once it's the active one
+        self.update_config(|config| Ok(config.set_active_storage(storage_name)?))?;
+
+        self.audit(Action::Open, storage_name, None, "");
+
+        Ok(())
+    }
+
+    /// Removes a storage and its files. Deleting the active storage leaves
+    /// no storage open.
+    pub fn delete(&mut self, storage_name: &str) -> Result<(), StorageManagerError> {
+        if !self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+
+        if self.is_active(storage_name) {
+            self.storage = None;
+        }
+
+        self.backing.remove(storage_name)?;
+        self.update_config(|config| Ok(config.remove_storage(storage_name)?))?;
+
+        self.audit(Action::Delete, storage_name, None, "");
+
+        Ok(())
+    }
+
+    pub fn rename(&mut self, storage_name: &str, new_storage_name: &str) -> Result<(), StorageManagerError> {
+        config::validate_storage_name(new_storage_name).map_err(ConfigError::from)?;
+        if !self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+        if self.config.has_storage(new_storage_name) {
+            return Err(ConfigError::StorageAlreadyExists.into());
+        }
+
+        let was_active = self.is_active(storage_name);
+        if was_active {
+            self.storage = None;
+        }
+
+        if let Err(e) = self.backing.rename(storage_name, new_storage_name) {
+            if was_active {
+                self.storage = Some(Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), storage_name)?);
+            }
+            return Err(e);
+        }
+
+        // Reopens the storage under its new name if it was active
+        self.update_config(|config| Ok(config.rename_storage(storage_name, new_storage_name)?))?;
+
+        self.audit(Action::Rename, storage_name, None, new_storage_name);
+
+        Ok(())
+    }
+
+    /// Copies a storage, records and cursor included, under a new name.
+    pub fn clone_storage(&mut self, storage_name: &str, new_storage_name: &str) -> Result<(), StorageManagerError> {
+        config::validate_storage_name(new_storage_name).map_err(ConfigError::from)?;
+        if !self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+        if self.config.has_storage(new_storage_name) {
+            return Err(ConfigError::StorageAlreadyExists.into());
+        }
+
+        if self.is_active(storage_name) {
+            self.get_active_storage()?.flush()?;
+        }
+
+        self.backing.copy(storage_name, new_storage_name)?;
+        self.update_config(|config| Ok(config.add_storage(new_storage_name)?))?;
+        self.audit(Action::Clone, storage_name, None, new_storage_name);
+
+        Ok(())
+    }
+
+    /// Copies what can still be read from a damaged storage into a new one,
+    /// leaving the damaged storage untouched.
+    pub fn salvage(&mut self, storage_name: &str, new_storage_name: &str) -> Result<SalvageReport, StorageManagerError> {
+        config::validate_storage_name(new_storage_name).map_err(ConfigError::from)?;
+        if !self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+        if self.config.has_storage(new_storage_name) {
+            return Err(ConfigError::StorageAlreadyExists.into());
+        }
+
+        if self.is_active(storage_name) {
+            self.get_active_storage()?.flush()?;
+        }
+
+        self.create(new_storage_name)?;
+
+        let mut source = Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), storage_name)?;
+        let mut destination = Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), new_storage_name)?;
+
+        Ok(source.salvage_into(&mut destination)?)
+    }
+
+    /// Appends the active records of `source` to `destination`.
+    pub fn merge(
+        &mut self,
+        source: &str,
+        destination: &str,
+        options: MergeOptions,
+    ) -> Result<MergeReport, StorageManagerError> {
+        for storage_name in [source, destination] {
+            if !self.config.has_storage(storage_name) {
+                return Err(ConfigError::StorageNotFound.into());
+            }
+        }
+        if source == destination {
+            return Err(StorageManagerError::SameStorage);
+        }
+
+        let records = Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), source)?.get_all()?;
+        let mut destination_storage = Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), destination)?;
+
+        let existing = destination_storage.get_all()?;
+        let records: Vec<Record> = records.into_iter().filter(|r| r.meta.is_active()).collect();
+
+        // Ids are checked before anything is written; a taken id is swapped
+        // for one past every id on either side, so it can't collide either
+        let mut taken_ids: HashSet<u64> = existing.iter().map(|r| r.meta.get_id()).collect();
+        let mut next_id =
+            taken_ids.iter().copied().chain(records.iter().map(|r| r.meta.get_id())).max().unwrap_or(0) + 1;
+        let mut seen: HashSet<String> = if options.dedupe {
+            existing.into_iter().filter(|r| r.meta.is_active()).map(|r| r.data).collect()
+        } else {
+            HashSet::new()
+        };
+
+        let mut report = MergeReport::default();
+
+        for record in records {
+            if options.dedupe && !seen.insert(record.data.clone()) {
+                report.skipped += 1;
+                continue;
+            }
+
+            if !options.keep_ids {
+                destination_storage.save(record.data)?;
+            } else if taken_ids.insert(record.meta.get_id()) {
+                destination_storage.save_with_id(record.data, record.meta.get_id())?;
+            } else {
+                destination_storage.save_with_id(record.data, next_id)?;
+                next_id += 1;
+                report.reassigned += 1;
+            }
+
+            report.added += 1;
+        }
+
+        destination_storage.flush()?;
+
+        if options.delete_source {
+            self.delete(source)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Saves the values read from `input` into the given storage, or the
+    /// active one. Nothing is saved if the input can't be read in full.
+    /// Records exported as inactive count as skipped.
+    pub fn import(
+        &mut self,
+        storage_name: Option<&str>,
+        input: impl BufRead,
+        format: ExportFormat,
+        dedupe: bool,
+    ) -> Result<ImportReport, StorageManagerError> {
+        if let Some(storage_name) = storage_name
+            && !self.config.has_storage(storage_name)
+        {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+
+        let imported = import::read_values(input, format)?;
+
+        let mut report = match storage_name {
+            Some(storage_name) if !self.is_active(storage_name) => {
+                Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), storage_name)?.import(imported.values, dedupe)?
+            }
+            _ => self.get_active_storage()?.import(imported.values, dedupe)?,
+        };
+        report.skipped += imported.inactive;
+
+        Ok(report)
+    }
+
+    /// Appends a record of the active storage to `destination` and
+    /// deactivates it in the active storage. Without an id, the record under
+    /// the cursor is moved.
+    pub fn move_record(&mut self, destination: &str, id: Option<u64>) -> Result<(), StorageManagerError> {
+        self.transfer_record(destination, id, true)
+    }
+
+    /// Like `move_record`, but leaves the record in the active storage.
+    pub fn copy_record(&mut self, destination: &str, id: Option<u64>) -> Result<(), StorageManagerError> {
+        self.transfer_record(destination, id, false)
+    }
+
+    /// Sets the storages `pick_rotation` and `move_next_rotation` cycle
+    /// through. An empty rotation turns round-robin off.
+    pub fn set_rotation(&mut self, rotation: Vec<RotationEntry>) -> Result<(), StorageManagerError> {
+        self.update_config(|config| Ok(config.set_rotation(rotation)?))?;
+        self.rotation_state = RotationState::default();
+
+        Ok(())
+    }
+
+    pub fn get_rotation(&self) -> &[RotationEntry] {
+        self.config.get_rotation()
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        !self.config.get_rotation().is_empty()
+    }
+
+    /// Current record of the rotation, along with the storage it's from.
+    /// Empty storages are skipped.
+    pub fn pick_rotation(&mut self) -> Result<(String, String), StorageManagerError> {
+        let (entry, _, record) = self.current_rotation_storage()?;
+        self.audit(Action::Pick, &entry.storage_name, Some(record.meta.get_id()), "");
+
+        Ok((entry.storage_name, record.data))
+    }
+
+    /// Moves past the current record of the rotation. Once a storage has
+    /// given as many records as its weight, the next storage takes its turn.
+    pub fn move_next_rotation(&mut self) -> Result<(), StorageManagerError> {
+        let (entry, mut storage, _) = self.current_rotation_storage()?;
+        storage.move_next()?;
+
+        self.rotation_state.taken += 1;
+        if self.rotation_state.taken >= entry.weight {
+            self.advance_rotation();
+        }
+
+        Ok(())
+    }
+
+    /// Entries of the audit log matching `filter`, oldest first. A manager
+    /// kept in memory logs nothing.
+    pub fn history(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, StorageManagerError> {
+        match &self.audit_log {
+            Some(audit_log) => Ok(audit_log.read(filter)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Writes the config and every storage, snapshots included, into one
+    /// archive. Returns how many storages it holds.
+    pub fn backup(&mut self, out: &mut impl Write) -> Result<u64, StorageManagerError> {
+        if let Some(storage) = self.storage.as_mut() {
+            storage.flush()?;
+        }
+
+        let mut files = vec![ArchiveFile {
+            path: Self::ARCHIVE_CONFIG_PATH.to_string(),
+            content: ConfigStore::serialize(&self.config).into_bytes(),
+        }];
+
+        let storage_list = self.config.get_storage_list().to_vec();
+        for storage_name in &storage_list {
+            let StorageFiles { meta, data, snapshots } = self.backing.read_files(storage_name)?;
+            files.push(ArchiveFile { path: format!("storage/{storage_name}.mt"), content: meta });
+            files.push(ArchiveFile { path: format!("storage/{storage_name}.dt"), content: data });
+            if let Some(snapshots) = snapshots {
+                files.push(ArchiveFile { path: format!("storage/{storage_name}.{}", snapshot::SIDECAR), content: snapshots });
+            }
+        }
+
+        backup::write_archive(out, &files)?;
+
+        Ok(storage_list.len() as u64)
+    }
+
+    /// Replaces the config and the storages with those in an archive made by
+    /// `backup`. The whole archive is read and every storage in it checked
+    /// first, so nothing is replaced unless all of it can be restored.
+    pub fn restore(&mut self, input: &mut impl BufRead) -> Result<RestoreReport, StorageManagerError> {
+        let invalid = |message: String| StorageManagerError::from(BackupError::InvalidArchive(message));
+
+        let mut config = None;
+        let mut storages = BTreeMap::<String, [Option<Vec<u8>>; 3]>::new();
+
+        for file in backup::read_archive(input)? {
+            let storage_file = file.path.strip_prefix("storage/").and_then(|name| {
+                let (storage_name, extension) = name.rsplit_once('.')?;
+                let slot = ["mt", "dt", snapshot::SIDECAR].iter().position(|known| *known == extension)?;
+                Some((storage_name, slot))
+            });
+
+            match storage_file {
+                _ if file.path == Self::ARCHIVE_CONFIG_PATH => {
+                    let content = String::from_utf8(file.content).map_err(|_| invalid("config is not text".to_string()))?;
+                    config = Some(ConfigStore::parse(&content).map_err(|e| invalid(format!("config: {e}")))?);
+                }
+                Some((storage_name, slot)) => storages.entry(storage_name.to_string()).or_default()[slot] = Some(file.content),
+                None => return Err(invalid(format!("unexpected file {}", file.path))),
+            }
+        }
+
+        let config = config.ok_or_else(|| invalid("no config".to_string()))?;
+        let mut restored = Vec::new();
+
+        for storage_name in config.get_storage_list() {
+            let Some([Some(meta), Some(data), snapshots]) = storages.remove(storage_name) else {
+                return Err(invalid(format!("files of storage {storage_name} are missing")));
+            };
+            if ![Meta::LEGACY_SIZE, Meta::size()].contains(&meta.len()) {
+                return Err(invalid(format!("meta of storage {storage_name} is damaged")));
+            }
+
+            let backend = MemoryBackend::from_parts(Meta::from_bytes(&meta), data.clone());
+            if !Storage::with_backend(Box::new(backend)).verify()?.is_ok() {
+                return Err(invalid(format!("storage {storage_name} is damaged")));
+            }
+            if let Some(snapshots) = &snapshots
+                && std::str::from_utf8(snapshots).ok().and_then(|content| snapshot::parse(content).ok()).is_none()
+            {
+                return Err(invalid(format!("snapshots of storage {storage_name} are damaged")));
+            }
+
+            restored.push((storage_name.clone(), meta, data, snapshots));
+        }
+
+        if let Some(storage_name) = storages.keys().next() {
+            return Err(invalid(format!("storage {storage_name} is not in the config")));
+        }
+
+        self.storage = None;
+        self.rotation_state = RotationState::default();
+
+        let mut report = RestoreReport::default();
+        for (storage_name, meta, data, snapshots) in restored {
+            self.backing.write_files(&storage_name, &meta, &data, snapshots.as_deref())?;
+            report.restored.push(storage_name);
+        }
+
+        report.unregistered = self.update_config(|current| {
+            let unregistered = current
+                .get_storage_list()
+                .iter()
+                .filter(|storage_name| !config.has_storage(storage_name))
+                .cloned()
+                .collect();
+
+            *current = config;
+            Ok(unregistered)
+        })?;
+
+        Ok(report)
+    }
+
+    /// Reconciles the config with the storage directory: storages whose
+    /// files are there but that the config lost are registered again, and
+    /// the ones whose files are gone are reported.
+    pub fn discover(&mut self) -> Result<DiscoveryReport, StorageManagerError> {
+        let found = self.backing.scan()?;
+
+        self.update_config(|config| {
+            let mut report = DiscoveryReport::default();
+
+            for (storage_name, files) in &found {
+                if config.has_storage(storage_name) || config::validate_storage_name(storage_name).is_err() {
+                    continue;
+                }
+
+                if files.is_complete() {
+                    config.add_storage(storage_name)?;
+                    report.registered.push(storage_name.clone());
+                } else {
+                    report.incomplete.push(storage_name.clone());
+                }
+            }
+
+            for storage_name in config.get_storage_list() {
+                if !found.get(storage_name).is_some_and(FoundFiles::is_complete) {
+                    report.missing.push(storage_name.clone());
+                }
+            }
+
+            Ok(report)
+        })
+    }
+
+    pub fn get_storage_settings(&self, storage_name: &str) -> Result<StorageSettings, StorageManagerError> {
+        if !self.config.has_storage(storage_name) {
+            return Err(ConfigError::StorageNotFound.into());
         }
+
+        Ok(self.config.get_storage_settings(storage_name))
     }
 
-    pub fn create(&mut self, storage_name: &str) {
-        Self::create_or_open_storage(storage_name);
+    pub fn set_storage_settings(&mut self, storage_name: &str, settings: StorageSettings) -> Result<(), StorageManagerError> {
+        self.update_config(|config| Ok(config.set_storage_settings(storage_name, settings)?))?;
 
-        let _ = self.config.set_active_storage(storage_name);
-        let _ = self.config.add_storage(storage_name);
+        if self.is_active(storage_name)
+            && let Some(storage) = self.storage.as_mut()
+        {
+            storage.set_durability(settings.durability);
+            storage.set_max_records(settings.max_records);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_active_storage(&mut self) -> Result<&mut Storage, StorageManagerError> {
+        self.storage.as_mut().ok_or(StorageManagerError::NoActiveStorage)
+    }
+
+    pub fn get_active_storage_name(&self) -> Result<String, StorageManagerError> {
+        self.config.get_active_storage().ok_or(StorageManagerError::NoActiveStorage)
+    }
+
+    pub fn get_list(&self) -> &[String] {
+        self.config.get_storage_list()
+    }
+
+    pub fn has_storages(&self) -> bool {
+        self.config.has_storages()
+    }
 
-        self.persist();
+    /// See `Config::invalid_storage_names`.
+    pub fn invalid_storage_names(&self) -> Vec<(String, InvalidStorageName)> {
+        self.config.invalid_storage_names()
     }
 
-    pub fn open(&mut self, storage_name: &str) {
-        self.storage = Some(Self::create_or_open_storage(storage_name));
-        let _ = self.config.set_active_storage(storage_name);
-        self.persist();
+    fn transfer_record(&mut self, destination: &str, id: Option<u64>, remove: bool) -> Result<(), StorageManagerError> {
+        if !self.config.has_storage(destination) {
+            return Err(ConfigError::StorageNotFound.into());
+        }
+        if self.is_active(destination) {
+            return Err(StorageManagerError::SameStorage);
+        }
+
+        let storage = self.get_active_storage()?;
+        let record = match id {
+            Some(id) => storage.get_by_id(id)?,
+            None => storage.current_record()?,
+        };
+
+        // Save first, so a failure can only leave a duplicate behind, never lose the record
+        Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), destination)?.save(record.data)?;
+
+        if remove {
+            self.get_active_storage()?.remove(record.meta.get_id())?;
+        }
+
+        Ok(())
     }
 
-    pub fn get_active_storage(&mut self) -> &mut Storage {
-        self.storage.as_mut().unwrap()
+    /// Opens a storage with its configured settings applied.
+    fn open_storage(
+        backing: &mut Backing,
+        config: &Config,
+        audit_log: Option<&AuditLog>,
+        storage_name: &str,
+    ) -> Result<Storage, StorageManagerError> {
+        let mut storage = backing.open(storage_name)?;
+        let settings = config.get_storage_settings(storage_name);
+
+        storage.set_durability(settings.durability);
+        storage.set_max_records(settings.max_records);
+        if let Some(audit_log) = audit_log {
+            storage.set_audit_log(audit_log.clone(), storage_name);
+        }
+
+        Ok(storage)
     }
 
-    pub fn get_active_storage_name(&mut self) -> String {
-        self.config.get_active_storage().unwrap()
+    fn current_rotation_storage(&mut self) -> Result<(RotationEntry, Storage, Record), StorageManagerError> {
+        let rotation = self.config.get_rotation().to_vec();
+        if rotation.is_empty() {
+            return Err(StorageManagerError::NoRotation);
+        }
+
+        for _ in 0..rotation.len() {
+            let entry = &rotation[self.rotation_state.position % rotation.len()];
+            let mut storage =
+                Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), &entry.storage_name)?;
+
+            // Looking for a storage that has a record isn't a pick
+            match storage.current_record() {
+                Ok(record) => return Ok((entry.clone(), storage, record)),
+                Err(StorageError::Empty) => self.advance_rotation(),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(StorageError::Empty.into())
     }
 
-    pub fn get_list(&mut self) -> &[String] {
-        &self.config.get_storage_list()
+    fn advance_rotation(&mut self) {
+        let len = self.config.get_rotation().len().max(1);
+        self.rotation_state.position = (self.rotation_state.position + 1) % len;
+        self.rotation_state.taken = 0;
     }
 
-    pub fn has_storages(&mut self) -> bool {
-        !self.config.get_storage_list().is_empty()
+    /// Like `Storage`, logging is best effort: the change is already made.
+    fn audit(&self, action: Action, storage_name: &str, record_id: Option<u64>, detail: &str) {
+        if let Some(audit_log) = &self.audit_log {
+            let _ = audit_log.record(action, storage_name, record_id, detail);
+        }
     }
 
-    fn create_or_open_storage(storage_name: &str) -> Storage {
-        Storage::new(
-            Self::STORAGE_DIR_PATH,
-            format!("{storage_name}.mt").as_str(),
-            format!("{storage_name}.dt").as_str(),
-        ).unwrap()
+    fn is_active(&self, storage_name: &str) -> bool {
+        self.config.get_active_storage().as_deref() == Some(storage_name)
     }
 
-    fn persist(&self) {
-        ConfigStore::persist(Self::CONFIG_PATH, &self.config).unwrap()
+    /// Applies `change` to the config as it is on disk right now, under its
+    /// lock, so changes other processes made since this one read it aren't
+    /// lost. The active storage is reopened if the change, or another
+    /// process, switched it.
+    fn update_config<T>(
+        &mut self,
+        change: impl FnOnce(&mut Config) -> Result<T, StorageManagerError>,
+    ) -> Result<T, StorageManagerError> {
+        let active_before = self.config.get_active_storage();
+
+        let result = match &self.backing {
+            Backing::Files(paths) => {
+                let (config, result) = ConfigStore::update(&paths.config_path, change)?;
+                self.config = config;
+                result
+            }
+            Backing::Memory(_) => change(&mut self.config)?,
+        };
+
+        let active = self.config.get_active_storage();
+        if active != active_before || (self.storage.is_none() && active.is_some()) {
+            self.storage = None;
+            if let Some(storage_name) = active {
+                self.storage = Some(Self::open_storage(&mut self.backing, &self.config, self.audit_log.as_ref(), &storage_name)?);
+            }
+        }
+
+        Ok(result)
     }
+}
 
-    const STORAGE_DIR_PATH: &str = "storage";
-    const CONFIG_PATH: &str = "config";
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_storages_survive_switching() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("first").unwrap();
+        storage_manager.open("first").unwrap();
+        storage_manager.get_active_storage().unwrap().save("value".to_string()).unwrap();
+
+        storage_manager.create("second").unwrap();
+        storage_manager.open("second").unwrap();
+        storage_manager.open("first").unwrap();
+
+        assert_eq!(storage_manager.get_active_storage().unwrap().pick().unwrap(), "value");
+        assert_eq!(storage_manager.get_list(), ["first", "second"]);
+    }
+
+    #[test]
+    fn open_unknown_storage_fails() {
+        let mut storage_manager = StorageManager::in_memory();
+
+        assert!(matches!(
+            storage_manager.open("missing"),
+            Err(StorageManagerError::Config(ConfigError::StorageNotFound))
+        ));
+        assert!(!storage_manager.has_storages());
+    }
+
+    #[test]
+    fn unsafe_names_are_refused() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("first").unwrap();
+
+        assert!(matches!(
+            storage_manager.create("../outside"),
+            Err(StorageManagerError::Config(ConfigError::InvalidStorageName(_)))
+        ));
+        assert!(storage_manager.rename("first", ".hidden").is_err());
+        assert!(storage_manager.clone_storage("first", "").is_err());
+        assert_eq!(storage_manager.get_list(), ["first"]);
+    }
+
+    #[test]
+    fn discover_registers_orphaned_storages() {
+        let root = std::env::temp_dir().join(format!("re-queue-discover-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let mut storage_manager = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        for storage_name in ["kept", "orphan", "gone"] {
+            storage_manager.create(storage_name).unwrap();
+        }
+        fs::remove_file(root.join("config")).unwrap();
+        fs::remove_file(root.join("config.bak")).unwrap();
+        fs::remove_file(root.join("storage/gone.mt")).unwrap();
+        fs::remove_file(root.join("storage/gone.dt")).unwrap();
+        fs::write(root.join("storage/half.mt"), []).unwrap();
+
+        let mut storage_manager = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        storage_manager.create("gone").unwrap();
+        fs::remove_file(root.join("storage/gone.dt")).unwrap();
+        for extension in ["mt", "dt"] {
+            fs::copy(root.join(format!("storage/kept.{extension}")), root.join(format!("storage/.old.{extension}"))).unwrap();
+        }
+        storage_manager.update_config(|config| Ok(config.add_existing_storage(".old")?)).unwrap();
+        let report = storage_manager.discover().unwrap();
+
+        assert_eq!(report.registered, ["kept", "orphan"]);
+        assert_eq!(report.missing, ["gone"]);
+        assert_eq!(report.incomplete, ["half"]);
+        assert_eq!(storage_manager.get_list(), ["gone", ".old", "kept", "orphan"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn only_picks_are_audited_as_picks() {
+        let root = std::env::temp_dir().join(format!("re-queue-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let mut storage_manager = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        storage_manager.create("inbox").unwrap();
+        storage_manager.create("later").unwrap();
+        storage_manager.open("inbox").unwrap();
+        for value in ["a", "b"] {
+            storage_manager.get_active_storage().unwrap().save(value.to_string()).unwrap();
+        }
+
+        storage_manager.get_active_storage().unwrap().export(&mut Vec::new(), ExportFormat::Csv).unwrap();
+        storage_manager.move_record("later", None).unwrap();
+        storage_manager
+            .set_rotation(vec![RotationEntry { storage_name: "later".to_string(), weight: 1 }])
+            .unwrap();
+        storage_manager.pick_rotation().unwrap();
+        storage_manager.clone_storage("inbox", "copy").unwrap();
+
+        let actions: Vec<(Action, String)> = storage_manager
+            .history(&AuditFilter { action: Some(Action::Pick), ..AuditFilter::default() })
+            .unwrap()
+            .into_iter()
+            .chain(storage_manager.history(&AuditFilter { action: Some(Action::Clone), ..AuditFilter::default() }).unwrap())
+            .map(|entry| (entry.action, entry.storage_name + " " + &entry.detail))
+            .collect();
+        assert_eq!(actions, [(Action::Pick, "later ".to_string()), (Action::Clone, "inbox copy".to_string())]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failed_rename_moves_files_back() {
+        let root = std::env::temp_dir().join(format!("re-queue-rename-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let mut storage_manager = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        storage_manager.create("old").unwrap();
+        storage_manager.open("old").unwrap();
+        storage_manager.get_active_storage().unwrap().save("value".to_string()).unwrap();
+        fs::remove_file(root.join("storage/old.dt")).unwrap();
+
+        assert!(storage_manager.rename("old", "new").is_err());
+        assert!(root.join("storage/old.journal").exists());
+        assert!(root.join("storage/old.mt").exists());
+        assert!(!root.join("storage/new.journal").exists());
+        assert_eq!(storage_manager.get_list(), ["old"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn concurrent_managers_keep_each_others_changes() {
+        let root = std::env::temp_dir().join(format!("re-queue-concurrent-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let mut first = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        let mut second = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        first.create("a").unwrap();
+        second.create("b").unwrap();
+        first.create("c").unwrap();
+        second.delete("a").unwrap();
+        first.open("c").unwrap();
+
+        let reloaded = StorageManager::new(StoragePaths::from_root(&root)).unwrap();
+        assert_eq!(reloaded.get_list(), ["b", "c"]);
+        assert_eq!(first.get_list(), ["b", "c"]);
+        assert_eq!(reloaded.get_active_storage_name().unwrap(), "c");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn delete_active_storage_closes_it() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("first").unwrap();
+        storage_manager.open("first").unwrap();
+        storage_manager.delete("first").unwrap();
+
+        assert!(!storage_manager.has_storages());
+        assert!(matches!(
+            storage_manager.get_active_storage(),
+            Err(StorageManagerError::NoActiveStorage)
+        ));
+    }
+
+    #[test]
+    fn rename_keeps_records_and_active() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("first").unwrap();
+        storage_manager.open("first").unwrap();
+        storage_manager.get_active_storage().unwrap().save("value".to_string()).unwrap();
+        storage_manager.rename("first", "renamed").unwrap();
+
+        assert_eq!(storage_manager.get_active_storage_name().unwrap(), "renamed");
+        assert_eq!(storage_manager.get_active_storage().unwrap().pick().unwrap(), "value");
+    }
+
+    #[test]
+    fn clone_is_independent_copy() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("first").unwrap();
+        storage_manager.open("first").unwrap();
+        storage_manager.get_active_storage().unwrap().save("value".to_string()).unwrap();
+        storage_manager.clone_storage("first", "copy").unwrap();
+        storage_manager.get_active_storage().unwrap().save("other".to_string()).unwrap();
+
+        storage_manager.open("copy").unwrap();
+        assert_eq!(storage_manager.get_active_storage().unwrap().get_all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn merge_dedupes_and_deletes_source() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("sprint").unwrap();
+        storage_manager.create("backlog").unwrap();
+
+        storage_manager.open("sprint").unwrap();
+        for value in ["a", "b", "b"] {
+            storage_manager.get_active_storage().unwrap().save(value.to_string()).unwrap();
+        }
+        storage_manager.open("backlog").unwrap();
+        storage_manager.get_active_storage().unwrap().save("a".to_string()).unwrap();
+
+        let options = MergeOptions { dedupe: true, delete_source: true, ..Default::default() };
+        let report = storage_manager.merge("sprint", "backlog", options).unwrap();
+
+        assert_eq!(report, MergeReport { added: 1, skipped: 2, reassigned: 0 });
+        assert_eq!(storage_manager.get_list(), ["backlog"]);
+
+        let records = storage_manager.get_active_storage().unwrap().get_all().unwrap();
+        let values: Vec<&str> = records.iter().map(|r| r.data.as_str()).collect();
+        assert_eq!(values, ["a", "b"]);
+    }
+
+    #[test]
+    fn merge_can_keep_ids() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("source").unwrap();
+        storage_manager.create("destination").unwrap();
+
+        storage_manager.open("source").unwrap();
+        for value in ["a", "b", "c"] {
+            storage_manager.get_active_storage().unwrap().save(value.to_string()).unwrap();
+        }
+
+        let options = MergeOptions { keep_ids: true, ..Default::default() };
+        storage_manager.merge("source", "destination", options).unwrap();
+
+        storage_manager.open("destination").unwrap();
+        let storage = storage_manager.get_active_storage().unwrap();
+        storage.save("d".to_string()).unwrap();
+
+        let ids: Vec<u64> = storage.get_all().unwrap().iter().map(|r| r.meta.get_id()).collect();
+        assert_eq!(ids, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn merge_reassigns_ids_the_destination_holds() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("source").unwrap();
+        storage_manager.create("destination").unwrap();
+
+        for (storage_name, values) in [("destination", ["x", "y"]), ("source", ["a", "b"])] {
+            storage_manager.open(storage_name).unwrap();
+            for value in values {
+                storage_manager.get_active_storage().unwrap().save(value.to_string()).unwrap();
+            }
+        }
+        storage_manager.get_active_storage().unwrap().save("c".to_string()).unwrap();
+
+        let options = MergeOptions { keep_ids: true, ..Default::default() };
+        let report = storage_manager.merge("source", "destination", options).unwrap();
+        assert_eq!(report, MergeReport { added: 3, skipped: 0, reassigned: 2 });
+
+        storage_manager.open("destination").unwrap();
+        let records = storage_manager.get_active_storage().unwrap().get_all().unwrap();
+        let ids: Vec<(&str, u64)> = records.iter().map(|r| (r.data.as_str(), r.meta.get_id())).collect();
+        assert_eq!(ids, [("x", 1), ("y", 2), ("a", 4), ("b", 5), ("c", 3)]);
+    }
+
+    #[test]
+    fn import_into_named_storage_dedupes() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("inbox").unwrap();
+        storage_manager.create("later").unwrap();
+        storage_manager.open("inbox").unwrap();
+
+        let input = "id,active,data\n1,true,a\n2,false,b\n3,true,a\n4,true,c\n";
+        let report = storage_manager.import(Some("later"), input.as_bytes(), ExportFormat::Csv, true).unwrap();
+
+        assert_eq!(report, ImportReport { added: 2, skipped: 2 });
+        assert!(storage_manager.get_active_storage().unwrap().get_all().unwrap().is_empty());
+
+        storage_manager.open("later").unwrap();
+        let records = storage_manager.get_active_storage().unwrap().get_all().unwrap();
+        let values: Vec<&str> = records.iter().map(|r| r.data.as_str()).collect();
+        assert_eq!(values, ["a", "c"]);
+    }
+
+    #[test]
+    fn restore_brings_back_backed_up_state() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("inbox").unwrap();
+        storage_manager.open("inbox").unwrap();
+        storage_manager.get_active_storage().unwrap().save("kept".to_string()).unwrap();
+        storage_manager.get_active_storage().unwrap().create_snapshot("before").unwrap();
+
+        let mut archive = Vec::new();
+        assert_eq!(storage_manager.backup(&mut archive).unwrap(), 1);
+
+        storage_manager.get_active_storage().unwrap().save("lost".to_string()).unwrap();
+        storage_manager.create("later").unwrap();
+
+        let mut damaged = archive.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 1;
+        assert!(storage_manager.restore(&mut damaged.as_slice()).is_err());
+        assert_eq!(storage_manager.get_list(), ["inbox", "later"]);
+
+        let report = storage_manager.restore(&mut archive.as_slice()).unwrap();
+        assert_eq!(report, RestoreReport { restored: vec!["inbox".to_string()], unregistered: vec!["later".to_string()] });
+        assert_eq!(storage_manager.get_active_storage().unwrap().get_all().unwrap().len(), 1);
+        assert_eq!(storage_manager.get_active_storage().unwrap().get_snapshots().unwrap()[0].name, "before");
+    }
+
+    #[test]
+    fn move_record_deactivates_source_copy() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("inbox").unwrap();
+        storage_manager.create("later").unwrap();
+        storage_manager.open("inbox").unwrap();
+        for value in ["a", "b"] {
+            storage_manager.get_active_storage().unwrap().save(value.to_string()).unwrap();
+        }
+
+        storage_manager.move_record("later", None).unwrap();
+        storage_manager.copy_record("later", Some(2)).unwrap();
+
+        assert_eq!(storage_manager.get_active_storage().unwrap().pick().unwrap(), "b");
+
+        storage_manager.open("later").unwrap();
+        let records = storage_manager.get_active_storage().unwrap().get_all().unwrap();
+        let values: Vec<&str> = records.iter().map(|r| r.data.as_str()).collect();
+        assert_eq!(values, ["a", "b"]);
+    }
+
+    #[test]
+    fn rotation_interleaves_weighted_storages() {
+        let mut storage_manager = StorageManager::in_memory();
+        for (storage_name, values) in [("work", ["w1", "w2"]), ("home", ["h1", "h2"])] {
+            storage_manager.create(storage_name).unwrap();
+            storage_manager.open(storage_name).unwrap();
+            for value in values {
+                storage_manager.get_active_storage().unwrap().save(value.to_string()).unwrap();
+            }
+        }
+        storage_manager.create("empty").unwrap();
+
+        let entry = |storage_name: &str, weight| RotationEntry { storage_name: storage_name.to_string(), weight };
+        storage_manager
+            .set_rotation(vec![entry("empty", 1), entry("work", 2), entry("home", 1)])
+            .unwrap();
+
+        let mut picked = Vec::new();
+        for _ in 0..4 {
+            picked.push(storage_manager.pick_rotation().unwrap().1);
+            storage_manager.move_next_rotation().unwrap();
+        }
+
+        assert_eq!(picked, ["w1", "w2", "h1", "w1"]);
+    }
+
+    #[test]
+    fn active_storage_requires_open() {
+        let mut storage_manager = StorageManager::in_memory();
+        storage_manager.create("first").unwrap();
+
+        assert!(matches!(
+            storage_manager.get_active_storage(),
+            Err(StorageManagerError::NoActiveStorage)
+        ));
+    }
 }